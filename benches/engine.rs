@@ -0,0 +1,72 @@
+//! Compares the interpreter with the decoded and compiled engines on the brute-force search of day 2, where every
+//! instruction runs once per program, and on day 9, which spends most of its time in loops.
+
+#![feature(test)]
+extern crate test;
+
+use adventofcode::intcode::{parse_program, Computer, Engine, Memory, Status, Value};
+use std::fs;
+use test::{black_box, Bencher};
+
+fn search(program: &Memory, engine: Engine) -> Option<Value> {
+    for noun in 0..100 {
+        for verb in 0..100 {
+            let mut memory = program.clone();
+            *memory.get_mut(1) = noun;
+            *memory.get_mut(2) = verb;
+            let mut computer = Computer::load(memory);
+            computer.set_engine(engine);
+            if computer.resume() == Ok(Status::Halted) && computer.memory().get(0) == 19_690_720 {
+                return Some(100 * noun + verb);
+            }
+        }
+    }
+    None
+}
+
+fn run(program: &Memory, engine: Engine, input: Value) -> Status {
+    let mut computer = Computer::load(program.clone());
+    computer.set_engine(engine);
+    computer.provide_input(input);
+    computer.resume().unwrap()
+}
+
+fn load(day: &str) -> Memory {
+    parse_program(&fs::read_to_string(format!("input/{}", day)).unwrap()).unwrap()
+}
+
+#[bench]
+fn day_02_interpreter(b: &mut Bencher) {
+    let program = load("day2");
+    b.iter(|| black_box(search(&program, Engine::Interpreter)));
+}
+
+#[bench]
+fn day_02_decoded(b: &mut Bencher) {
+    let program = load("day2");
+    b.iter(|| black_box(search(&program, Engine::Decoded)));
+}
+
+#[bench]
+fn day_09_interpreter(b: &mut Bencher) {
+    let program = load("day9");
+    b.iter(|| black_box(run(&program, Engine::Interpreter, 2)));
+}
+
+#[bench]
+fn day_09_decoded(b: &mut Bencher) {
+    let program = load("day9");
+    b.iter(|| black_box(run(&program, Engine::Decoded, 2)));
+}
+
+#[bench]
+fn day_02_compiled(b: &mut Bencher) {
+    let program = load("day2");
+    b.iter(|| black_box(search(&program, Engine::Compiled)));
+}
+
+#[bench]
+fn day_09_compiled(b: &mut Bencher) {
+    let program = load("day9");
+    b.iter(|| black_box(run(&program, Engine::Compiled, 2)));
+}
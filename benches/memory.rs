@@ -0,0 +1,153 @@
+//! Compares the paged `Memory` with the previous design, which kept every cell beyond the image in
+//! a `HashMap`. The memory accesses of the day 9 and day 13 programs are recorded once and then
+//! replayed against both.
+
+#![feature(test)]
+extern crate test;
+
+use adventofcode::intcode::{parse_program, Computer, Memory, Status, TraceEntry, Value};
+use std::collections::HashMap;
+use std::fs;
+use test::{black_box, Bencher};
+
+/// The memory as it was before it was paged.
+struct HashMapMemory {
+    base: Vec<Value>,
+    additional: HashMap<usize, Value>,
+}
+
+impl HashMapMemory {
+    fn get(&self, pos: usize) -> Value {
+        *self
+            .base
+            .get(pos)
+            .unwrap_or_else(|| self.additional.get(&pos).unwrap_or(&0))
+    }
+
+    fn get_mut(&mut self, pos: usize) -> &mut Value {
+        if let Some(val) = self.base.get_mut(pos) {
+            val
+        } else {
+            self.additional.entry(pos).or_insert(0)
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Access {
+    Read(usize),
+    Write(usize, Value),
+}
+
+fn load(day: &str) -> Memory {
+    parse_program(&fs::read_to_string(format!("input/{}", day)).unwrap()).unwrap()
+}
+
+/// Run a program, choosing every input from the outputs so far, and record its memory accesses.
+fn record(memory: Memory, mut input: impl FnMut(&[Value]) -> Value) -> Vec<Access> {
+    let mut accesses = Vec::new();
+    let mut tracer = |entry: &TraceEntry| {
+        for read in &entry.step.reads {
+            accesses.push(Access::Read(read.address));
+        }
+        if let Some(write) = entry.step.write {
+            accesses.push(Access::Write(write.address, write.new));
+        }
+    };
+    let mut computer = Computer::load(memory);
+    computer.set_tracer(Some(&mut tracer));
+    let mut outputs = Vec::new();
+    loop {
+        match computer.resume().unwrap() {
+            Status::NeedsInput => computer.provide_input(input(&outputs)),
+            Status::Output(value) => outputs.push(value),
+            Status::Halted => break,
+        }
+    }
+    drop(computer);
+    accesses
+}
+
+fn day_09() -> (Memory, Vec<Access>) {
+    let memory = load("day9");
+    let accesses = record(memory.clone(), |_| 2);
+    (memory, accesses)
+}
+
+fn day_13() -> (Memory, Vec<Access>) {
+    let mut memory = load("day13");
+    *memory.get_mut(0) = 2;
+    // Move the paddle towards the ball
+    let accesses = record(memory.clone(), |outputs| {
+        let x_of = |tile| {
+            outputs
+                .chunks(3)
+                .rev()
+                .find(|chunk| chunk[0] >= 0 && chunk[2] == tile)
+                .map_or(0, |chunk| chunk[0])
+        };
+        (x_of(4) - x_of(3)).signum()
+    });
+    (memory, accesses)
+}
+
+fn replay_paged(memory: &Memory, accesses: &[Access]) -> Value {
+    let mut memory = memory.clone();
+    let mut total: Value = 0;
+    for access in accesses {
+        match *access {
+            Access::Read(address) => total = total.wrapping_add(memory.get(address)),
+            Access::Write(address, value) => *memory.get_mut(address) = value,
+        }
+    }
+    total
+}
+
+fn replay_hash_map(memory: &Memory, accesses: &[Access]) -> Value {
+    let mut memory = HashMapMemory {
+        base: memory.base().to_vec(),
+        additional: HashMap::new(),
+    };
+    let mut total: Value = 0;
+    for access in accesses {
+        match *access {
+            Access::Read(address) => total = total.wrapping_add(memory.get(address)),
+            Access::Write(address, value) => *memory.get_mut(address) = value,
+        }
+    }
+    total
+}
+
+#[bench]
+fn day_09_paged(b: &mut Bencher) {
+    let (memory, accesses) = day_09();
+    b.iter(|| black_box(replay_paged(&memory, &accesses)));
+}
+
+#[bench]
+fn day_09_hash_map(b: &mut Bencher) {
+    let (memory, accesses) = day_09();
+    b.iter(|| black_box(replay_hash_map(&memory, &accesses)));
+}
+
+#[bench]
+fn day_13_paged(b: &mut Bencher) {
+    let (memory, accesses) = day_13();
+    b.iter(|| black_box(replay_paged(&memory, &accesses)));
+}
+
+#[bench]
+fn day_13_hash_map(b: &mut Bencher) {
+    let (memory, accesses) = day_13();
+    b.iter(|| black_box(replay_hash_map(&memory, &accesses)));
+}
+
+#[bench]
+fn day_09_run(b: &mut Bencher) {
+    let memory = load("day9");
+    b.iter(|| {
+        let mut computer = Computer::load(memory.clone());
+        computer.provide_input(2);
+        black_box(computer.resume().unwrap())
+    });
+}
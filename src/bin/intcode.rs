@@ -0,0 +1,35 @@
+//! Runs an Intcode program against stdin and stdout.
+//!
+//! ```text
+//! intcode <program> [--ascii]
+//! ```
+//!
+//! Input is read as one value per line, or as text with `--ascii`. For example,
+//! `echo 5 | intcode input/day5` runs the diagnostics of day 5.
+
+use adventofcode::intcode::{parse_program, Computer, StreamInput, StreamMode, StreamOutput};
+use anyhow::{anyhow, Result};
+use std::env;
+use std::fs;
+
+const USAGE: &str = "usage: intcode <program> [--ascii]";
+
+fn main() -> Result<()> {
+    let mut path = None;
+    let mut mode = StreamMode::Numeric;
+    for argument in env::args().skip(1) {
+        match argument.as_str() {
+            "--ascii" => mode = StreamMode::Ascii,
+            _ if path.is_none() => path = Some(argument),
+            _ => return Err(anyhow!(USAGE)),
+        }
+    }
+    let path = path.ok_or_else(|| anyhow!(USAGE))?;
+    let text = fs::read_to_string(&path).map_err(|error| anyhow!("{}: {}", path, error))?;
+    let mut input = StreamInput::stdin(mode);
+    let mut output = StreamOutput::stdout(mode);
+    let mut computer = Computer::load(parse_program(&text)?);
+    computer.set_input(Some(&mut input));
+    computer.set_output(Some(&mut output));
+    futures::executor::block_on(computer.run()).map_err(|error| anyhow!(error.report()))
+}
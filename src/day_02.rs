@@ -1,4 +1,4 @@
-use crate::intcode::{parse_program, Computer, ComputerError, Memory, Value};
+use crate::intcode::{parse_program, Computer, ComputerError, Memory, SymbolicComputer, Value};
 
 pub fn parse_input(s: &str) -> Result<Memory, ComputerError> {
     parse_program(s)
@@ -12,7 +12,25 @@ pub async fn part_1(mut input: Memory) -> Result<Value, ComputerError> {
     Ok(computer.memory().get(0))
 }
 
+const TARGET: Value = 19_690_720;
+
+/// Solve for the noun and verb, if the output depends linearly on them.
+fn solve_symbolically(input: &Memory) -> Option<Value> {
+    let mut computer = SymbolicComputer::load(input.clone());
+    computer.set_symbol(1, "noun");
+    computer.set_symbol(2, "verb");
+    computer.run().ok()?;
+    let bounds = vec![("noun", 0..=99), ("verb", 0..=99)]
+        .into_iter()
+        .collect();
+    let solution = computer.get(0).linear()?.solve(TARGET, &bounds)?;
+    Some(100 * solution.get("noun").unwrap_or(&0) + solution.get("verb").unwrap_or(&0))
+}
+
 pub async fn part_2(input: Memory) -> Result<Value, ComputerError> {
+    if let Some(answer) = solve_symbolically(&input) {
+        return Ok(answer);
+    }
     for i in 0..100 {
         for j in 0..100 {
             let mut input = input.clone();
@@ -20,7 +38,7 @@ pub async fn part_2(input: Memory) -> Result<Value, ComputerError> {
             *input.get_mut(2) = j;
             let mut computer = Computer::load(input);
             computer.run().await?;
-            if computer.memory().get(0) == 19_690_720 {
+            if computer.memory().get(0) == TARGET {
                 return Ok(100 * i + j);
             }
         }
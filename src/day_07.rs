@@ -1,4 +1,4 @@
-use crate::intcode::{parse_program, Computer, ComputerError, Memory, Value};
+use crate::intcode::{parse_program, Computer, ComputerError, Engine, Memory, Value};
 use futures::channel::mpsc::{channel, Receiver, SendError, Sender};
 use futures::prelude::*;
 use permutohedron::Heap;
@@ -20,6 +20,7 @@ pub struct Solution {}
 async fn output(memory: Memory, mut input: &[Value]) -> Result<Value, ComputerError> {
     let mut output_a = None;
     let mut comp = Computer::load(memory);
+    comp.set_engine(Engine::Compiled);
     comp.set_input(Some(&mut input));
     comp.set_output(Some(&mut output_a));
     comp.run().await?;
@@ -74,6 +75,7 @@ pub async fn part_2(parsed_input: &Memory) -> Result<Value, SolutionError> {
                     sender.send(0).await?;
                 }
                 let mut comp = Computer::load(parsed_input.clone());
+                comp.set_engine(Engine::Compiled);
                 comp.set_input(Some(&mut receiver));
                 comp.set_output(Some(&mut sender));
                 comp.run().await?;
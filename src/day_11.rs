@@ -1,4 +1,4 @@
-use crate::intcode::{Computer, ComputerError, Memory, Value};
+use crate::intcode::{Computer, ComputerError, Connection, Memory, Recorder, Transcript, Value};
 use futures::channel::mpsc::{channel, Receiver, Sender};
 use futures::future::try_select;
 use futures::future::Either;
@@ -85,12 +85,17 @@ impl Direction {
 struct EmergencyHullPaintingRobot {}
 
 impl EmergencyHullPaintingRobot {
-    async fn run(memory: Memory, field: &mut Field) -> Result<(), SolutionError> {
-        let (to_robot_sender, mut to_robot_receiver) = channel(CHANNEL_BUFFER_SIZE);
-        let (mut from_robot_sender, from_robot_receiver) = channel(CHANNEL_BUFFER_SIZE);
+    /// Paint `field`, recording the input and output of the robot with `recorder`, if any.
+    async fn run(
+        memory: Memory,
+        field: &mut Field,
+        recorder: Option<&Recorder>,
+    ) -> Result<(), SolutionError> {
+        let (to_robot_sender, to_robot_receiver) = channel(CHANNEL_BUFFER_SIZE);
+        let (from_robot_sender, from_robot_receiver) = channel(CHANNEL_BUFFER_SIZE);
+        let mut connection = Connection::new(to_robot_receiver, from_robot_sender, recorder);
         let mut computer = Computer::load(memory);
-        computer.set_input(Some(&mut to_robot_receiver));
-        computer.set_output(Some(&mut from_robot_sender));
+        connection.attach(&mut computer);
         let computer_future = computer.run();
         let mut field_runner = FieldRunner::new(field);
         let field_runner_future = field_runner.run(to_robot_sender, from_robot_receiver);
@@ -237,13 +242,22 @@ pub use crate::intcode::parse_program as parse_input;
 
 pub async fn part_1(memory: Memory) -> Result<usize, SolutionError> {
     let mut field = Field::new();
-    EmergencyHullPaintingRobot::run(memory, &mut field).await?;
+    EmergencyHullPaintingRobot::run(memory, &mut field, None).await?;
     Ok(field.unique_tiles_painted())
 }
 
 pub async fn part_2(memory: Memory) -> Result<Field, SolutionError> {
     let mut field = Field::new();
     field.paint(Point::new(0, 0), Color::White);
-    EmergencyHullPaintingRobot::run(memory, &mut field).await?;
+    EmergencyHullPaintingRobot::run(memory, &mut field, None).await?;
     Ok(field)
 }
+
+/// Run part 2 and return the input and output of the robot.
+pub async fn record_part_2(memory: Memory) -> Result<Transcript, SolutionError> {
+    let mut field = Field::new();
+    field.paint(Point::new(0, 0), Color::White);
+    let recorder = Recorder::new();
+    EmergencyHullPaintingRobot::run(memory, &mut field, Some(&recorder)).await?;
+    Ok(recorder.transcript())
+}
@@ -1,5 +1,7 @@
-use crate::intcode::{io, Computer, ComputerError, Memory, Value};
-use async_trait::async_trait;
+use crate::intcode::{
+    Chunks, Computer, ComputerError, Connection, Engine, IoError, Memory, ReadFn, Recorder,
+    Transcript, Value,
+};
 use futures::channel::mpsc::{channel, Receiver};
 use futures::prelude::*;
 use futures::select;
@@ -7,7 +9,7 @@ use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
 use std::fmt;
-use std::sync::{Arc, Mutex};
+use std::sync::Mutex;
 use thiserror::*;
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
@@ -49,6 +51,13 @@ pub enum SolutionError {
     CouldNotFindTileId,
 }
 
+/// Errors of the game, while the cabinet reads or writes it.
+impl From<SolutionError> for IoError {
+    fn from(error: SolutionError) -> Self {
+        IoError::Other(error.to_string())
+    }
+}
+
 impl TryFrom<Value> for TileId {
     type Error = SolutionError;
     fn try_from(input: Value) -> Result<Self, Self::Error> {
@@ -151,13 +160,10 @@ const CHANNEL_BUFFER_SIZE: usize = 1;
 struct GameState {
     screen: Screen,
     score: u64,
-    input: [Value; 3],
-    input_index: usize,
 }
 
 impl GameState {
-    fn handle_input(&mut self) -> Result<(), SolutionError> {
-        let [x, y, z] = self.input;
+    fn handle_output(&mut self, (x, y, z): (Value, Value, Value)) -> Result<(), SolutionError> {
         if x == -1 && y == 0 {
             self.score = z.try_into().map_err(|_| SolutionError::ProtocolError)?;
         } else {
@@ -171,6 +177,17 @@ impl GameState {
     fn score(&self) -> u64 {
         self.score
     }
+
+    /// Move the paddle towards the ball.
+    fn joystick(&self) -> Result<JoystickPosition, SolutionError> {
+        let paddle_pos = self.screen.paddle_position()?.x;
+        let ball_pos = self.screen.ball_position()?.x;
+        Ok(match paddle_pos.cmp(&ball_pos) {
+            Ordering::Less => JoystickPosition::Right,
+            Ordering::Greater => JoystickPosition::Left,
+            Ordering::Equal => JoystickPosition::Neutral,
+        })
+    }
 }
 
 enum JoystickPosition {
@@ -189,39 +206,9 @@ impl From<JoystickPosition> for Value {
     }
 }
 
-#[async_trait]
-impl io::Read for Arc<Mutex<GameState>> {
-    async fn read(&mut self) -> Option<Value> {
-        let screen = &self.lock().unwrap().screen;
-        let paddle_pos = screen.paddle_position().unwrap().x;
-        let ball_pos = screen.ball_position().unwrap().x;
-        Some(
-            match paddle_pos.cmp(&ball_pos) {
-                Ordering::Less => JoystickPosition::Right,
-                Ordering::Greater => JoystickPosition::Left,
-                Ordering::Equal => JoystickPosition::Neutral,
-            }
-            .into(),
-        )
-    }
-}
-
-#[async_trait]
-impl io::Write for Arc<Mutex<GameState>> {
-    async fn write(&mut self, value: Value) {
-        let mut state = self.lock().unwrap();
-        let input_index = state.input_index;
-        state.input[input_index] = value;
-        state.input_index += 1;
-        if state.input_index > 2 {
-            state.input_index = 0;
-            state.handle_input().unwrap();
-        }
-    }
-}
-
 pub async fn part_1(memory: Memory) -> Result<usize, SolutionError> {
     let mut computer = Computer::load(memory);
+    computer.set_engine(Engine::Compiled);
     let (mut output_sender, output_receiver) = channel(CHANNEL_BUFFER_SIZE);
     computer.set_output(Some(&mut output_sender));
 
@@ -233,14 +220,29 @@ pub async fn part_1(memory: Memory) -> Result<usize, SolutionError> {
     Ok(screen.block_tile_count())
 }
 
-pub async fn part_2(mut memory: Memory) -> Result<u64, SolutionError> {
-    let mut game_state = Arc::new(Mutex::new(GameState::default()));
-    let mut cloned_game_state = game_state.clone();
+pub async fn part_2(memory: Memory) -> Result<u64, SolutionError> {
+    play(memory, None).await
+}
+
+/// Run part 2 and return the input and output of the cabinet.
+pub async fn record_part_2(memory: Memory) -> Result<Transcript, SolutionError> {
+    let recorder = Recorder::new();
+    play(memory, Some(&recorder)).await?;
+    Ok(recorder.transcript())
+}
+
+/// Play the game for free, recording it with `recorder`, if any, and return the final score.
+async fn play(mut memory: Memory, recorder: Option<&Recorder>) -> Result<u64, SolutionError> {
+    let game_state = Mutex::new(GameState::default());
+    let input = ReadFn::new(|| Ok(Some(game_state.lock().unwrap().joystick()?.into())));
+    let output = Chunks::new(|tile| Ok(game_state.lock().unwrap().handle_output(tile)?));
+    let mut connection = Connection::new(input, output, recorder);
     *memory.get_mut(0) = 2;
     let mut computer = Computer::load(memory);
-    computer.set_output(Some(&mut cloned_game_state));
-    computer.set_input(Some(&mut game_state));
+    computer.set_engine(Engine::Compiled);
+    connection.attach(&mut computer);
     computer.run().await?;
+    drop(computer);
     let score = game_state.lock().unwrap().score();
     Ok(score)
 }
@@ -1,6 +1,8 @@
-use crate::intcode::{parse_program, Computer, ComputerError, Memory, Value};
+use crate::intcode::{
+    parse_program, Computer, ComputerError, Connection, Memory, Recorder, Transcript, TypedInput,
+    TypedOutput, Value,
+};
 use futures::channel::mpsc::{channel, SendError};
-use futures::pin_mut;
 use futures::prelude::*;
 use futures::select;
 use std::collections::VecDeque;
@@ -350,47 +352,63 @@ impl Map {
 const CHANNEL_SIZE: usize = 1;
 
 pub async fn part_1(input: Memory) -> Result<usize, SolutionError> {
-    let mut robot = Computer::load(input);
+    shortest_path(input, None).await
+}
 
-    let (command_sender, mut command_receiver) = channel(CHANNEL_SIZE);
-    let (mut status_sender, status_receiver) = channel(CHANNEL_SIZE);
+/// Run part 1 and return the input and output of the droid.
+pub async fn record_part_1(input: Memory) -> Result<Transcript, SolutionError> {
+    let recorder = Recorder::new();
+    shortest_path(input, Some(&recorder)).await?;
+    Ok(recorder.transcript())
+}
 
-    robot.set_input(Some(&mut command_receiver));
-    robot.set_output(Some(&mut status_sender));
+/// Find the oxygen system, recording the droid with `recorder`, if any.
+async fn shortest_path(input: Memory, recorder: Option<&Recorder>) -> Result<usize, SolutionError> {
+    let (command_sender, command_receiver) = channel(CHANNEL_SIZE);
+    let (status_sender, status_receiver) = channel(CHANNEL_SIZE);
 
-    let status_receiver =
-        status_receiver.map(|s: Value| -> Result<Status, SolutionError> { Status::try_from(s) });
-    let command_sender = command_sender.with(
-        async move |v: MovementCommand| -> Result<Value, SolutionError> { Ok(Value::from(v)) },
+    let mut connection = Connection::new(
+        TypedInput::new(command_receiver),
+        TypedOutput::new(status_sender),
+        recorder,
     );
-    pin_mut!(command_sender);
+    let mut robot = Computer::load(input);
+    connection.attach(&mut robot);
+    let status_receiver = status_receiver.map(Ok::<Status, SolutionError>);
+    let command_sender = command_sender.sink_map_err(SolutionError::from);
     let mut system = System::new(status_receiver, command_sender);
 
     select!(
         system_res = system.shortest_path_to_oxygen_system().fuse() => system_res,
-        _ = robot.run().fuse() => unreachable!()
+        // The droid never halts, but fails on an unknown status code
+        robot_res = robot.run().fuse() => {
+            robot_res?;
+            unreachable!()
+        }
     )
 }
 
 pub async fn part_2(input: Memory) -> Result<u32, SolutionError> {
     let mut robot = Computer::load(input);
 
-    let (command_sender, mut command_receiver) = channel(CHANNEL_SIZE);
-    let (mut status_sender, status_receiver) = channel(CHANNEL_SIZE);
+    let (command_sender, command_receiver) = channel(CHANNEL_SIZE);
+    let (status_sender, status_receiver) = channel(CHANNEL_SIZE);
 
+    let mut command_receiver = TypedInput::new(command_receiver);
+    let mut status_sender = TypedOutput::new(status_sender);
     robot.set_input(Some(&mut command_receiver));
     robot.set_output(Some(&mut status_sender));
 
-    let mut status_receiver =
-        status_receiver.map(|s: Value| -> Result<Status, SolutionError> { Status::try_from(s) });
-    let command_sender = command_sender.with(
-        async move |v: MovementCommand| -> Result<Value, SolutionError> { Ok(Value::from(v)) },
-    );
-    pin_mut!(command_sender);
+    let mut status_receiver = status_receiver.map(Ok::<Status, SolutionError>);
+    let mut command_sender = command_sender.sink_map_err(SolutionError::from);
 
     let map = select!(
         map_res = Map::build_from(&mut status_receiver, &mut command_sender).fuse() => map_res,
-        _ = robot.run().fuse() => unreachable!()
+        // The droid never halts, but fails on an unknown status code
+        robot_res = robot.run().fuse() => {
+            robot_res?;
+            unreachable!()
+        }
     )?;
 
     map.flood_fill()
@@ -1,54 +1,79 @@
-use std::collections::HashMap;
+use arrayvec::ArrayVec;
+use std::collections::VecDeque;
 use std::convert::TryFrom;
-use std::iter;
+use std::fmt;
+use std::str::FromStr;
 use thiserror::*;
 
+pub mod assembler;
+mod cell;
+pub mod cfg;
+pub mod code_map;
+mod compiler;
+pub mod coverage;
+pub mod debugger;
+mod decoded;
+pub mod decompiler;
+pub mod disassembler;
+pub mod history;
 pub mod io;
+pub mod memory;
+pub mod profiler;
+#[cfg(test)]
+mod samples;
+pub mod snapshot;
+pub mod symbolic;
+pub mod trace;
+pub mod transcript;
+pub use assembler::{assemble, AssembleError};
+pub use cell::Cell;
+pub use cfg::{analyze, ControlFlowGraph};
+pub use code_map::{CodeMap, SelfModification};
+use compiler::Compiler;
+pub use coverage::Coverage;
+pub use debugger::{Breakpoint, Debugger, Stop, Watch};
+use decoded::DecodeCache;
+pub use decompiler::{decompile, Decompiled};
+pub use disassembler::{decode, disassemble, Disassembly, Item, Parameter};
+pub use history::{History, HistoryLimits};
 pub use io::*;
+pub use memory::Memory;
+pub use profiler::Profiler;
+pub use snapshot::Snapshot;
+pub use symbolic::{SymbolicComputer, SymbolicError};
+pub use trace::{RingBuffer, TraceEntry, TraceWriter, Tracer};
+pub use transcript::{replay, Connection, Recorder, Replayer, Transcript};
 
-pub type Value = isize;
+/// The value of a memory cell, unless a `Computer` is given another `Cell` type. This is an `i64`
+/// regardless of the target, or an `i128` with the `i128` feature for programs that work with
+/// larger numbers.
+#[cfg(not(feature = "i128"))]
+pub type Value = i64;
+#[cfg(feature = "i128")]
+pub type Value = i128;
 
 pub fn parse_program(s: &str) -> Result<Memory, ComputerError> {
-    Ok(Memory::from(
-        s.trim()
-            .split(',')
-            .map(|s| {
-                s.parse::<Value>()
-                    .map_err(|_| ComputerError::ParseProgramError)
-            })
-            .collect::<Result<Vec<_>, ComputerError>>()?,
-    ))
+    s.parse()
 }
 
-#[derive(Clone, Debug)]
-pub struct Memory {
-    base: Vec<Value>,
-    additional: HashMap<usize, Value>,
-}
+/// Parses a program like `parse_program`, for any type of cell.
+impl<V: Cell> FromStr for Memory<V> {
+    type Err = ComputerError<V>;
 
-impl From<Vec<Value>> for Memory {
-    fn from(base: Vec<Value>) -> Self {
-        Memory {
-            base,
-            additional: HashMap::new(),
-        }
-    }
-}
-
-impl Memory {
-    pub fn get(&self, pos: usize) -> Value {
-        *self
-            .base
-            .get(pos)
-            .unwrap_or_else(|| self.additional.get(&pos).unwrap_or(&0))
-    }
-
-    pub fn get_mut(&mut self, pos: usize) -> &mut Value {
-        if let Some(val) = self.base.get_mut(pos) {
-            val
-        } else {
-            self.additional.entry(pos).or_insert(0)
-        }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Memory::from(
+            s.trim()
+                .split(',')
+                .enumerate()
+                .map(|(index, s)| {
+                    s.parse::<V>()
+                        .map_err(|_| ComputerError::ParseProgramError {
+                            index,
+                            text: s.to_string(),
+                        })
+                })
+                .collect::<Result<Vec<_>, ComputerError<V>>>()?,
+        ))
     }
 }
 
@@ -81,8 +106,8 @@ impl<Item, I: Iterator<Item = Result<Item, E>>, E: Clone> IteratorOkOrRepeatable
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-enum OpCode {
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum OpCode {
     Add,
     Multiply,
     Input,
@@ -95,52 +120,209 @@ enum OpCode {
     Quit,
 }
 
-#[derive(Clone, Copy, Debug)]
-enum ParameterMode {
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum ParameterMode {
     Position,
     Immediate,
     Relative,
 }
 
-#[derive(Error, Debug, Clone, Copy)]
-pub enum ComputerError {
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum ComputerError<V: Cell = Value> {
     #[error("unknown op code: {0}")]
-    UnknownOpCode(Value),
+    UnknownOpCode(V),
     #[error("unknown parameter mode: {0}")]
-    UnknownParameterMode(Value),
+    UnknownParameterMode(V),
     #[error("parameter write in immediate mode")]
     WriteInImmediateMode,
     #[error("expected parameter, but the memory stops here")]
     ExpectedParameter,
+    /// The input ended, or there is no reader.
     #[error("expected input")]
     ReadInputError,
+    #[error("could not read input: {0}")]
+    InputFailed(#[source] IoError),
+    #[error("could not write output: {0}")]
+    OutputFailed(#[source] IoError),
     #[error("attempted to write outside of memory")]
     WriteOutsideOfMemory,
     #[error("attempted to read outside of memory")]
     ReadOutsideOfMemory,
     #[error("an arithmatic error occurred")]
     ArithmaticError,
-    #[error("could not parse the program")]
-    ParseProgramError,
+    #[error("could not parse the program: value {index} is {text:?}")]
+    ParseProgramError { index: usize, text: String },
     #[error("jumped to invalid location")]
     InvalidJump,
+    #[error("exceeded the limit of {0} executed instructions")]
+    InstructionLimitExceeded(u64),
+    #[error("attempted to access memory beyond the limit of {0} cells")]
+    MemoryLimitExceeded(usize),
+    #[error("exceeded the limit of {0} outputs")]
+    OutputLimitExceeded(u64),
+    #[error("attempted to write to {0}, which was executed as code")]
+    CodeModified(usize),
+    /// An error that occurred while executing an instruction, with the state of the computer.
+    #[error("{error} ({context})")]
+    Fault {
+        #[source]
+        error: Box<Self>,
+        context: Box<ErrorContext<V>>,
+    },
+}
+
+impl<V: Cell> ComputerError<V> {
+    /// The underlying error, without the context it occurred in.
+    pub fn kind(&self) -> &ComputerError<V> {
+        match self {
+            ComputerError::Fault { error, .. } => error.kind(),
+            other => other,
+        }
+    }
+
+    pub fn context(&self) -> Option<&ErrorContext<V>> {
+        match self {
+            ComputerError::Fault { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// A description of the error, followed by the disassembly around the faulting instruction.
+    pub fn report(&self) -> String {
+        let mut report = format!("error: {}\n", self.kind());
+        if let Some(context) = self.context() {
+            report += &format!(
+                "at address {}, instruction {}, relative base {}\n",
+                context.instruction_pointer, context.instruction, context.relative_base
+            );
+            for item in &context.excerpt {
+                let marker = if item.address() == context.instruction_pointer {
+                    '>'
+                } else {
+                    ' '
+                };
+                report += &format!("{} {:>5}: {}\n", marker, item.address(), item);
+            }
+        }
+        report
+    }
+}
+
+/// Number of instructions shown before and after the faulting one in an error report.
+const EXCERPT_LENGTH: usize = 3;
+
+/// Number of cells before the faulting instruction that are decoded to find the instructions
+/// before it, so reporting an error does not take longer with more memory.
+const EXCERPT_WINDOW: usize = 64;
+
+/// The state of the computer when an instruction failed.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ErrorContext<V: Cell = Value> {
+    pub instruction_pointer: usize,
+    /// The raw value of the instruction, including the parameter modes.
+    pub instruction: V,
+    pub relative_base: V,
+    /// The parameters of the instruction, as far as they could be decoded.
+    pub parameters: ArrayVec<[Parameter<V>; 3]>,
+    /// The disassembly around the instruction.
+    pub excerpt: Vec<Item<V>>,
+}
+
+impl<V: Cell> fmt::Display for ErrorContext<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "at address {}: instruction {}, relative base {}",
+            self.instruction_pointer, self.instruction, self.relative_base
+        )?;
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            write!(
+                f,
+                "{}{}",
+                if i == 0 { ", parameters " } else { ", " },
+                parameter
+            )?;
+        }
+        Ok(())
+    }
 }
 
 impl TryFrom<Value> for OpCode {
     type Error = ComputerError;
     fn try_from(u: Value) -> Result<Self, Self::Error> {
-        match u {
-            1 => Ok(OpCode::Add),
-            2 => Ok(OpCode::Multiply),
-            3 => Ok(OpCode::Input),
-            4 => Ok(OpCode::Output),
-            5 => Ok(OpCode::JumpIfTrue),
-            6 => Ok(OpCode::JumpIfFalse),
-            7 => Ok(OpCode::LessThan),
-            8 => Ok(OpCode::Equals),
-            9 => Ok(OpCode::RelativeBaseOffset),
-            99 => Ok(OpCode::Quit),
-            other => Err(ComputerError::UnknownOpCode(other)),
+        u.to_i64()
+            .and_then(OpCode::from_code)
+            .ok_or(ComputerError::UnknownOpCode(u))
+    }
+}
+
+impl OpCode {
+    pub(crate) fn from_code(code: i64) -> Option<Self> {
+        match code {
+            1 => Some(OpCode::Add),
+            2 => Some(OpCode::Multiply),
+            3 => Some(OpCode::Input),
+            4 => Some(OpCode::Output),
+            5 => Some(OpCode::JumpIfTrue),
+            6 => Some(OpCode::JumpIfFalse),
+            7 => Some(OpCode::LessThan),
+            8 => Some(OpCode::Equals),
+            9 => Some(OpCode::RelativeBaseOffset),
+            99 => Some(OpCode::Quit),
+            _ => None,
+        }
+    }
+
+    /// The number of parameters following the instruction.
+    pub fn parameter_count(self) -> usize {
+        match self {
+            OpCode::Add | OpCode::Multiply | OpCode::LessThan | OpCode::Equals => 3,
+            OpCode::JumpIfTrue | OpCode::JumpIfFalse => 2,
+            OpCode::Input | OpCode::Output | OpCode::RelativeBaseOffset => 1,
+            OpCode::Quit => 0,
+        }
+    }
+
+    /// The short name used in assembly.
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            OpCode::Add => "add",
+            OpCode::Multiply => "mul",
+            OpCode::Input => "in",
+            OpCode::Output => "out",
+            OpCode::JumpIfTrue => "jt",
+            OpCode::JumpIfFalse => "jf",
+            OpCode::LessThan => "lt",
+            OpCode::Equals => "eq",
+            OpCode::RelativeBaseOffset => "rbo",
+            OpCode::Quit => "hlt",
+        }
+    }
+}
+
+impl From<OpCode> for Value {
+    fn from(op_code: OpCode) -> Value {
+        match op_code {
+            OpCode::Add => 1,
+            OpCode::Multiply => 2,
+            OpCode::Input => 3,
+            OpCode::Output => 4,
+            OpCode::JumpIfTrue => 5,
+            OpCode::JumpIfFalse => 6,
+            OpCode::LessThan => 7,
+            OpCode::Equals => 8,
+            OpCode::RelativeBaseOffset => 9,
+            OpCode::Quit => 99,
+        }
+    }
+}
+
+impl From<ParameterMode> for Value {
+    fn from(mode: ParameterMode) -> Value {
+        match mode {
+            ParameterMode::Position => 0,
+            ParameterMode::Immediate => 1,
+            ParameterMode::Relative => 2,
         }
     }
 }
@@ -148,206 +330,857 @@ impl TryFrom<Value> for OpCode {
 impl TryFrom<Value> for ParameterMode {
     type Error = ComputerError;
     fn try_from(u: Value) -> Result<Self, Self::Error> {
-        match u {
-            0 => Ok(ParameterMode::Position),
-            1 => Ok(ParameterMode::Immediate),
-            2 => Ok(ParameterMode::Relative),
-            other => Err(ComputerError::UnknownParameterMode(other)),
+        u.to_i64()
+            .and_then(ParameterMode::from_code)
+            .ok_or(ComputerError::UnknownParameterMode(u))
+    }
+}
+
+impl ParameterMode {
+    pub(crate) fn from_code(code: i64) -> Option<Self> {
+        match code {
+            0 => Some(ParameterMode::Position),
+            1 => Some(ParameterMode::Immediate),
+            2 => Some(ParameterMode::Relative),
+            _ => None,
         }
     }
 }
 
-#[derive(Copy, Clone)]
-struct Instruction(Value);
+#[derive(Clone)]
+struct Instruction<V: Cell = Value>(V);
 
-#[derive(Copy, Clone)]
-struct ModesIterator(Value);
+#[derive(Clone)]
+struct ModesIterator<V: Cell>(V);
 
-impl Iterator for ModesIterator {
-    type Item = Result<ParameterMode, ComputerError>;
-    fn next(&mut self) -> Option<Result<ParameterMode, ComputerError>> {
-        let out = ParameterMode::try_from(self.0 % 10);
-        self.0 /= 10;
-        Some(out)
+impl<V: Cell> Iterator for ModesIterator<V> {
+    type Item = Result<ParameterMode, ComputerError<V>>;
+    fn next(&mut self) -> Option<Result<ParameterMode, ComputerError<V>>> {
+        let (rest, mode) = self.0.div_rem(10);
+        self.0 = rest;
+        Some(
+            ParameterMode::from_code(mode)
+                .ok_or_else(|| ComputerError::UnknownParameterMode(V::from(mode))),
+        )
     }
 }
 
 impl Instruction {
-    fn op_code(self) -> Result<OpCode, ComputerError> {
-        OpCode::try_from(self.0 % 100)
+    fn encode(op_code: OpCode, modes: impl IntoIterator<Item = ParameterMode>) -> Self {
+        let (value, _) = modes
+            .into_iter()
+            .fold((Value::from(op_code), 100), |(value, factor), mode| {
+                (value + factor * Value::from(mode), factor * 10)
+            });
+        Instruction(value)
+    }
+}
+
+impl<V: Cell> Instruction<V> {
+    fn op_code(&self) -> Result<OpCode, ComputerError<V>> {
+        let (_, code) = self.0.div_rem(100);
+        OpCode::from_code(code).ok_or_else(|| ComputerError::UnknownOpCode(V::from(code)))
     }
 
-    fn modes(self) -> impl Iterator<Item = Result<ParameterMode, ComputerError>> {
-        ModesIterator(self.0 / 100)
+    fn modes(&self) -> impl Iterator<Item = Result<ParameterMode, ComputerError<V>>> {
+        ModesIterator(self.0.div_rem(100).0)
     }
 }
 
-pub struct Computer<'a> {
-    memory: Memory,
+/// The address of a relative parameter, which has to lie within memory.
+fn relative_address<V: Cell>(relative_base: &V, offset: &V) -> Result<usize, ComputerError<V>> {
+    let address = relative_base
+        .checked_add(offset)
+        .ok_or(ComputerError::ArithmaticError)?;
+    address.to_usize().ok_or(ComputerError::ReadOutsideOfMemory)
+}
+
+/// A memory cell read by one of the parameters of an instruction.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct MemoryRead<V: Cell = Value> {
+    pub address: usize,
+    pub value: V,
+}
+
+/// A memory cell written by an instruction.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct MemoryWrite<V: Cell = Value> {
+    pub address: usize,
+    pub old: V,
+    pub new: V,
+}
+
+/// A report of a single instruction executed by `Computer::step`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Step<V: Cell = Value> {
+    /// The address of the executed instruction.
+    pub address: usize,
+    pub op_code: OpCode,
+    /// The value that was read, for input instructions.
+    pub input: Option<V>,
+    /// The value that was written, for output instructions.
+    pub output: Option<V>,
+    /// The memory cells read by position and relative parameters, in order.
+    pub reads: ArrayVec<[MemoryRead<V>; 2]>,
+    /// The memory cell written to, if any.
+    pub write: Option<MemoryWrite<V>>,
+}
+
+impl<V: Cell> Step<V> {
+    pub fn halted(&self) -> bool {
+        self.op_code == OpCode::Quit
+    }
+}
+
+/// The reason `Computer::resume` returned.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Status<V: Cell = Value> {
+    /// The program wants to read, but no input was provided.
+    NeedsInput,
+    /// The program produced an output value.
+    Output(V),
+    Halted,
+}
+
+/// Resource limits for running untrusted programs. `None` means unlimited.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub struct Limits {
+    /// The maximum number of instructions to execute.
+    pub instructions: Option<u64>,
+    /// The number of memory cells that may be addressed, starting from zero.
+    pub memory: Option<usize>,
+    /// The maximum number of values to output.
+    pub outputs: Option<u64>,
+}
+
+/// How a `Computer` executes instructions. Both engines give exactly the same results.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Engine {
+    /// Decode every instruction each time it is executed.
+    Interpreter,
+    /// Keep decoded instructions, dropping them when the program writes over its own code.
+    Decoded,
+    /// Translate runs of instructions without input or output into closures, interpreting the
+    /// rest. Only used by `run` and `resume` without a tracer, limits, history or tracking of
+    /// self-modification.
+    Compiled,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::Decoded
+    }
+}
+
+/// Runs an Intcode program, keeping cells of type `V` in its memory.
+pub struct Computer<'a, V: Cell = Value> {
+    memory: Memory<V>,
     instruction_pointer: usize,
-    relative_base: Value,
-    read: Option<&'a mut (dyn Read + 'a)>,
-    write: Option<&'a mut (dyn Write + 'a)>,
+    relative_base: V,
+    read: Option<&'a mut (dyn Read<V> + 'a)>,
+    write: Option<&'a mut (dyn Write<V> + 'a)>,
+    pending_input: VecDeque<V>,
+    tracer: Option<&'a mut (dyn Tracer<V> + 'a)>,
+    limits: Limits,
+    executed_instructions: u64,
+    outputs: u64,
+    /// Whether the quit instruction at the instruction pointer was executed.
+    halted: bool,
+    engine: Engine,
+    decoded: DecodeCache<V>,
+    compiler: Compiler<V>,
+    self_modification: SelfModification,
+    code_map: Option<CodeMap<V>>,
+    history: Option<History<V>>,
 }
 
-impl<'a> Computer<'a> {
-    pub fn load(memory: Memory) -> Self {
+impl<'a, V: Cell> Computer<'a, V> {
+    pub fn load(memory: Memory<V>) -> Self {
         Computer {
             memory,
             instruction_pointer: 0,
-            relative_base: 0,
+            relative_base: V::default(),
             read: None,
             write: None,
+            pending_input: VecDeque::new(),
+            tracer: None,
+            limits: Limits::default(),
+            executed_instructions: 0,
+            outputs: 0,
+            halted: false,
+            engine: Engine::default(),
+            decoded: DecodeCache::default(),
+            compiler: Compiler::default(),
+            self_modification: SelfModification::default(),
+            code_map: None,
+            history: None,
         }
     }
 
-    fn advance_pointer(&mut self) -> Value {
-        let val = self.memory.get(self.instruction_pointer as usize);
+    fn advance_pointer(&mut self) -> V {
+        let val = self.memory.get(self.instruction_pointer);
         self.instruction_pointer += 1;
         val
     }
 
-    pub fn memory(&self) -> &Memory {
+    pub fn memory(&self) -> &Memory<V> {
         &self.memory
     }
 
-    pub fn set_input(&mut self, read: Option<&'a mut (dyn Read + 'a)>) {
+    pub fn set_input(&mut self, read: Option<&'a mut (dyn Read<V> + 'a)>) {
         self.read = read;
     }
 
-    pub fn set_output(&mut self, write: Option<&'a mut (dyn Write + 'a)>) {
+    pub fn set_output(&mut self, write: Option<&'a mut (dyn Write<V> + 'a)>) {
         self.write = write;
     }
 
-    pub async fn run(&mut self) -> Result<(), ComputerError> {
+    /// Record every executed instruction. Without a tracer, no trace entries are built at all.
+    pub fn set_tracer(&mut self, tracer: Option<&'a mut (dyn Tracer<V> + 'a)>) {
+        self.tracer = tracer;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    /// Start or stop tracking executed and written cells. Tracking starts with an empty
+    /// `CodeMap`, and stopping it drops the map.
+    pub fn set_self_modification(&mut self, self_modification: SelfModification) {
+        self.self_modification = self_modification;
+        self.code_map = match self_modification {
+            SelfModification::Allow => None,
+            _ => self.code_map.take().or_else(|| Some(CodeMap::default())),
+        };
+    }
+
+    /// The cells executed and written since tracking started with `set_self_modification`, or
+    /// since the state was last restored or stepped back.
+    pub fn code_map(&self) -> Option<&CodeMap<V>> {
+        self.code_map.as_ref()
+    }
+
+    /// Start tracking over, as the map no longer matches a state that was changed behind its back.
+    pub(crate) fn clear_code_map(&mut self) {
+        if self.code_map.is_some() {
+            self.code_map = Some(CodeMap::default());
+        }
+    }
+
+    /// The number of instructions executed since the program was loaded.
+    pub fn executed_instructions(&self) -> u64 {
+        self.executed_instructions
+    }
+
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    pub fn relative_base(&self) -> V {
+        self.relative_base.clone()
+    }
+
+    /// Run the program until it halts.
+    pub async fn run(&mut self) -> Result<(), ComputerError<V>> {
         loop {
-            let instruction_value = self.advance_pointer();
-            let instruction = Instruction(instruction_value);
-            let mut parameters = instruction
-                .modes()
-                .zip(iter::from_fn(|| Some(self.advance_pointer())))
-                .map(|(a, b)| Ok((a?, b)))
-                .ok_or_repeat(ComputerError::ExpectedParameter);
-            match instruction.op_code()? {
-                OpCode::Quit => return Ok(()),
-                OpCode::Add => {
-                    let a_at = parameters.next()?;
-                    let b_at = parameters.next()?;
-                    let to_at = parameters.next()?;
-                    let a = self.get_parameter(a_at)?;
-                    let b = self.get_parameter(b_at)?;
-                    let to = self.get_parameter_mut(to_at)?;
-                    *to = a.checked_add(b).ok_or(ComputerError::ArithmaticError)?;
-                }
-                OpCode::Multiply => {
-                    let a_at = parameters.next()?;
-                    let b_at = parameters.next()?;
-                    let to_at = parameters.next()?;
-                    let a = self.get_parameter(a_at)?;
-                    let b = self.get_parameter(b_at)?;
-                    let to = self.get_parameter_mut(to_at)?;
-                    *to = a.checked_mul(b).ok_or(ComputerError::ArithmaticError)?;
-                }
-                OpCode::Input => {
-                    let to_at = parameters.next()?;
-                    let value = self
-                        .read
-                        .as_mut()
-                        .ok_or(ComputerError::ReadInputError)?
-                        .read()
-                        .await
-                        .ok_or(ComputerError::ReadInputError)?;
-                    let to = self.get_parameter_mut(to_at)?;
-                    *to = value;
-                }
-                OpCode::Output => {
-                    let from_at = parameters.next()?;
-                    let from = self.get_parameter(from_at)?;
-                    if let Some(ref mut output) = self.write {
-                        output.write(from).await;
-                    }
-                }
-                OpCode::JumpIfTrue => {
-                    let a_at = parameters.next()?;
-                    let b_at = parameters.next()?;
-                    let a = self.get_parameter(a_at)?;
-                    let b = self.get_parameter(b_at)?;
-                    if a != 0 {
-                        self.instruction_pointer =
-                            usize::try_from(b).map_err(|_| ComputerError::InvalidJump)?;
-                    }
+            self.run_compiled()?;
+            if self.step().await?.halted() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Execute exactly one instruction, reading input and writing output as required.
+    ///
+    /// Once the program has halted, the instruction pointer stays at the quit instruction, so
+    /// stepping again will just report the halt again, without counting another instruction.
+    pub async fn step(&mut self) -> Result<Step<V>, ComputerError<V>> {
+        let address = self.instruction_pointer;
+        let result = self.step_instruction().await;
+        result.map_err(|error| self.fault(address, error))
+    }
+
+    async fn step_instruction(&mut self) -> Result<Step<V>, ComputerError<V>> {
+        let input = if self.next_op_code()? == OpCode::Input {
+            // Input queued again by `reverse_step` comes first
+            let value = match self.pending_input.pop_front() {
+                Some(value) => value,
+                None => self
+                    .read
+                    .as_mut()
+                    .ok_or(ComputerError::ReadInputError)?
+                    .read()
+                    .await
+                    .map_err(ComputerError::InputFailed)?
+                    .ok_or(ComputerError::ReadInputError)?,
+            };
+            Some(value)
+        } else {
+            None
+        };
+        let step = self.execute(input)?;
+        if let Some(output) = &step.output {
+            if let Some(ref mut write) = self.write {
+                if let Err(error) = write.write(output.clone()).await {
+                    self.take_back_output();
+                    return Err(ComputerError::OutputFailed(error));
                 }
-                OpCode::JumpIfFalse => {
-                    let a_at = parameters.next()?;
-                    let b_at = parameters.next()?;
-                    let a = self.get_parameter(a_at)?;
-                    let b = self.get_parameter(b_at)?;
-                    if a == 0 {
-                        self.instruction_pointer =
-                            usize::try_from(b).map_err(|_| ComputerError::InvalidJump)?;
+            }
+        }
+        Ok(step)
+    }
+
+    /// Take back the last executed instruction, an output that could not be written, so the next
+    /// step executes it again. Only the tracer has already seen it.
+    fn take_back_output(&mut self) {
+        self.forget_history();
+        self.executed_instructions -= 1;
+        self.outputs -= 1;
+    }
+
+    /// Queue a value to be read by `resume`. `step` and `run` read queued values before using
+    /// their reader.
+    pub fn provide_input(&mut self, value: V) {
+        self.pending_input.push_back(value);
+    }
+
+    /// Run synchronously until the program needs input that was not provided yet, produces an
+    /// output or halts. Input is taken from values given to `provide_input` and output is
+    /// returned, so the readers and writers set with `set_input` and `set_output` are not used.
+    pub fn resume(&mut self) -> Result<Status<V>, ComputerError<V>> {
+        loop {
+            self.run_compiled()?;
+            let address = self.instruction_pointer;
+            match self.resume_instruction() {
+                Ok(Some(status)) => return Ok(status),
+                Ok(None) => {}
+                Err(error) => return Err(self.fault(address, error)),
+            }
+        }
+    }
+
+    /// Execute a single instruction for `resume`, returning the status if it should stop.
+    fn resume_instruction(&mut self) -> Result<Option<Status<V>>, ComputerError<V>> {
+        let input = if self.next_op_code()? == OpCode::Input {
+            match self.pending_input.pop_front() {
+                Some(value) => Some(value),
+                None => return Ok(Some(Status::NeedsInput)),
+            }
+        } else {
+            None
+        };
+        let step = self.execute(input)?;
+        Ok(if let Some(output) = step.output {
+            Some(Status::Output(output))
+        } else if step.halted() {
+            Some(Status::Halted)
+        } else {
+            None
+        })
+    }
+
+    /// Add context to an error of the instruction at `address`, and move the instruction pointer
+    /// back to it so the state can be inspected.
+    fn fault(&mut self, address: usize, error: ComputerError<V>) -> ComputerError<V> {
+        self.instruction_pointer = address;
+        let mut excerpt = Vec::new();
+        let mut next = address.saturating_sub(EXCERPT_WINDOW);
+        while next < address {
+            let item = decode(&self.memory, next);
+            next += item.size();
+            if next <= address {
+                excerpt.push(item);
+            }
+        }
+        excerpt.drain(..excerpt.len().saturating_sub(EXCERPT_LENGTH));
+        let mut next = address;
+        for i in 0..=EXCERPT_LENGTH {
+            if i > 0 && next >= self.memory.base().len() {
+                break;
+            }
+            let item = decode(&self.memory, next);
+            next += item.size();
+            excerpt.push(item);
+        }
+        ComputerError::Fault {
+            error: Box::new(error),
+            context: Box::new(ErrorContext {
+                instruction_pointer: address,
+                instruction: self.memory.get(address),
+                relative_base: self.relative_base.clone(),
+                parameters: self.next_parameters(),
+                excerpt,
+            }),
+        }
+    }
+
+    fn next_op_code(&self) -> Result<OpCode, ComputerError<V>> {
+        Instruction(self.memory.get(self.instruction_pointer)).op_code()
+    }
+
+    /// Execute the instruction at the instruction pointer. If it is an input instruction, `input`
+    /// should contain the value to store. If the instruction fails, the input is queued again.
+    fn execute(&mut self, input: Option<V>) -> Result<Step<V>, ComputerError<V>> {
+        let result = self.execute_traced(input.clone());
+        if let (Err(_), Some(input)) = (&result, input) {
+            self.pending_input.push_front(input);
+        }
+        result
+    }
+
+    fn execute_traced(&mut self, input: Option<V>) -> Result<Step<V>, ComputerError<V>> {
+        if self.halted {
+            return Ok(Step {
+                address: self.instruction_pointer,
+                op_code: OpCode::Quit,
+                input: None,
+                output: None,
+                reads: ArrayVec::new(),
+                write: None,
+            });
+        }
+        if self.history.is_none() {
+            return self.trace_instruction(input);
+        }
+        self.checkpoint_history();
+        let relative_base = self.relative_base.clone();
+        let step = self.trace_instruction(input)?;
+        self.record_history(&step, relative_base);
+        Ok(step)
+    }
+
+    fn trace_instruction(&mut self, input: Option<V>) -> Result<Step<V>, ComputerError<V>> {
+        if self.tracer.is_none() {
+            return self.execute_instruction(input);
+        }
+        let parameters = self.next_parameters();
+        let relative_base_before = self.relative_base.clone();
+        let step = self.execute_instruction(input)?;
+        let entry = TraceEntry {
+            step,
+            parameters,
+            relative_base_before,
+            relative_base: self.relative_base.clone(),
+        };
+        if let Some(ref mut tracer) = self.tracer {
+            tracer.trace(&entry);
+        }
+        Ok(entry.step)
+    }
+
+    /// Decode the parameters of the next instruction, as far as they are valid.
+    fn next_parameters(&self) -> ArrayVec<[Parameter<V>; 3]> {
+        let instruction = Instruction(self.memory.get(self.instruction_pointer));
+        let count = instruction.op_code().map_or(0, OpCode::parameter_count);
+        let mut parameters = ArrayVec::new();
+        for (i, mode) in instruction.modes().take(count).enumerate() {
+            match mode {
+                Ok(mode) => parameters.push(Parameter {
+                    mode,
+                    value: self.memory.get(self.instruction_pointer + 1 + i),
+                }),
+                Err(_) => break,
+            }
+        }
+        parameters
+    }
+
+    fn execute_instruction(&mut self, input: Option<V>) -> Result<Step<V>, ComputerError<V>> {
+        let address = self.instruction_pointer;
+        if let Some(limit) = self.limits.instructions {
+            if self.executed_instructions >= limit {
+                return Err(ComputerError::InstructionLimitExceeded(limit));
+            }
+        }
+        self.check_address(address)?;
+        let cached = match self.engine {
+            Engine::Decoded | Engine::Compiled => self.decoded.get(&self.memory, address),
+            Engine::Interpreter => None,
+        };
+        let (op_code, parameters) = match cached {
+            Some(decoded) => {
+                self.instruction_pointer = address + 1 + decoded.op_code.parameter_count();
+                (decoded.op_code, decoded.parameters().map(Ok).collect())
+            }
+            None => {
+                let instruction = Instruction(self.advance_pointer());
+                let op_code = instruction.op_code()?;
+                let parameters: ArrayVec<[_; 3]> = instruction
+                    .modes()
+                    .take(op_code.parameter_count())
+                    .map(|mode| Ok((mode?, self.advance_pointer())))
+                    .collect();
+                (op_code, parameters)
+            }
+        };
+        let cells = address..self.instruction_pointer;
+        let mut parameters = parameters
+            .into_iter()
+            .ok_or_repeat(ComputerError::ExpectedParameter);
+        let mut step = Step {
+            address,
+            op_code,
+            input: input.clone(),
+            output: None,
+            reads: ArrayVec::new(),
+            write: None,
+        };
+        match op_code {
+            OpCode::Quit => {
+                self.instruction_pointer = address;
+                self.halted = true;
+            }
+            OpCode::Add => {
+                let a_at = parameters.next()?;
+                let b_at = parameters.next()?;
+                let to_at = parameters.next()?;
+                let a = self.get_parameter(a_at, &mut step)?;
+                let b = self.get_parameter(b_at, &mut step)?;
+                let value = a.checked_add(&b).ok_or(ComputerError::ArithmaticError)?;
+                self.set_parameter(to_at, value, &mut step)?;
+            }
+            OpCode::Multiply => {
+                let a_at = parameters.next()?;
+                let b_at = parameters.next()?;
+                let to_at = parameters.next()?;
+                let a = self.get_parameter(a_at, &mut step)?;
+                let b = self.get_parameter(b_at, &mut step)?;
+                let value = a.checked_mul(&b).ok_or(ComputerError::ArithmaticError)?;
+                self.set_parameter(to_at, value, &mut step)?;
+            }
+            OpCode::Input => {
+                let to_at = parameters.next()?;
+                let value = input.ok_or(ComputerError::ReadInputError)?;
+                self.set_parameter(to_at, value, &mut step)?;
+            }
+            OpCode::Output => {
+                let from_at = parameters.next()?;
+                if let Some(limit) = self.limits.outputs {
+                    if self.outputs >= limit {
+                        return Err(ComputerError::OutputLimitExceeded(limit));
                     }
                 }
-                OpCode::LessThan => {
-                    let a_at = parameters.next()?;
-                    let b_at = parameters.next()?;
-                    let c_at = parameters.next()?;
-                    let a = self.get_parameter(a_at)?;
-                    let b = self.get_parameter(b_at)?;
-                    let c = self.get_parameter_mut(c_at)?;
-                    *c = if a < b { 1 } else { 0 };
-                }
-                OpCode::Equals => {
-                    let a_at = parameters.next()?;
-                    let b_at = parameters.next()?;
-                    let c_at = parameters.next()?;
-                    let a = self.get_parameter(a_at)?;
-                    let b = self.get_parameter(b_at)?;
-                    let c = self.get_parameter_mut(c_at)?;
-                    *c = if a == b { 1 } else { 0 };
+                step.output = Some(self.get_parameter(from_at, &mut step)?);
+                self.outputs += 1;
+            }
+            OpCode::JumpIfTrue => {
+                let a_at = parameters.next()?;
+                let b_at = parameters.next()?;
+                let a = self.get_parameter(a_at, &mut step)?;
+                let b = self.get_parameter(b_at, &mut step)?;
+                if a != V::default() {
+                    self.instruction_pointer = b.to_usize().ok_or(ComputerError::InvalidJump)?;
                 }
-                OpCode::RelativeBaseOffset => {
-                    let at = parameters.next()?;
-                    let a = self.get_parameter(at)?;
-                    self.relative_base += a;
+            }
+            OpCode::JumpIfFalse => {
+                let a_at = parameters.next()?;
+                let b_at = parameters.next()?;
+                let a = self.get_parameter(a_at, &mut step)?;
+                let b = self.get_parameter(b_at, &mut step)?;
+                if a == V::default() {
+                    self.instruction_pointer = b.to_usize().ok_or(ComputerError::InvalidJump)?;
                 }
             }
+            OpCode::LessThan => {
+                let a_at = parameters.next()?;
+                let b_at = parameters.next()?;
+                let c_at = parameters.next()?;
+                let a = self.get_parameter(a_at, &mut step)?;
+                let b = self.get_parameter(b_at, &mut step)?;
+                self.set_parameter(c_at, V::from(i64::from(a < b)), &mut step)?;
+            }
+            OpCode::Equals => {
+                let a_at = parameters.next()?;
+                let b_at = parameters.next()?;
+                let c_at = parameters.next()?;
+                let a = self.get_parameter(a_at, &mut step)?;
+                let b = self.get_parameter(b_at, &mut step)?;
+                self.set_parameter(c_at, V::from(i64::from(a == b)), &mut step)?;
+            }
+            OpCode::RelativeBaseOffset => {
+                let at = parameters.next()?;
+                let a = self.get_parameter(at, &mut step)?;
+                self.relative_base = self
+                    .relative_base
+                    .checked_add(&a)
+                    .ok_or(ComputerError::ArithmaticError)?;
+            }
+        }
+        // Only instructions that succeed are counted, so a fault leaves the state as it was
+        self.executed_instructions += 1;
+        if let Some(code_map) = &mut self.code_map {
+            code_map.execute(cells);
         }
+        Ok(step)
     }
 
-    fn get_parameter(&self, (mode, pos): (ParameterMode, Value)) -> Result<Value, ComputerError> {
-        match mode {
-            ParameterMode::Immediate => Ok(pos),
-            ParameterMode::Position => {
-                let pos = usize::try_from(pos).map_err(|_| ComputerError::ReadOutsideOfMemory)?;
-                Ok(self.memory.get(pos))
-            }
-            ParameterMode::Relative => {
-                let pos = usize::try_from(self.relative_base + pos)
-                    .map_err(|_| ComputerError::ReadOutsideOfMemory)?;
-                Ok(self.memory.get(pos))
-            }
+    fn check_address(&self, address: usize) -> Result<(), ComputerError<V>> {
+        match self.limits.memory {
+            Some(limit) if address >= limit => Err(ComputerError::MemoryLimitExceeded(limit)),
+            _ => Ok(()),
         }
     }
 
-    fn get_parameter_mut(
+    fn get_parameter(
+        &self,
+        (mode, pos): (ParameterMode, V),
+        step: &mut Step<V>,
+    ) -> Result<V, ComputerError<V>> {
+        let address = match mode {
+            ParameterMode::Immediate => return Ok(pos),
+            ParameterMode::Position => pos.to_usize().ok_or(ComputerError::ReadOutsideOfMemory)?,
+            ParameterMode::Relative => relative_address(&self.relative_base, &pos)?,
+        };
+        self.check_address(address)?;
+        let value = self.memory.get(address);
+        step.reads.push(MemoryRead {
+            address,
+            value: value.clone(),
+        });
+        Ok(value)
+    }
+
+    fn set_parameter(
         &mut self,
-        (mode, pos): (ParameterMode, Value),
-    ) -> Result<&mut Value, ComputerError> {
-        match mode {
-            ParameterMode::Immediate => Err(ComputerError::WriteInImmediateMode),
-            ParameterMode::Position => {
-                let pos = usize::try_from(pos).map_err(|_| ComputerError::ReadOutsideOfMemory)?;
-                Ok(self.memory.get_mut(pos))
+        (mode, pos): (ParameterMode, V),
+        value: V,
+        step: &mut Step<V>,
+    ) -> Result<(), ComputerError<V>> {
+        let address = match mode {
+            ParameterMode::Immediate => return Err(ComputerError::WriteInImmediateMode),
+            ParameterMode::Position => pos.to_usize().ok_or(ComputerError::ReadOutsideOfMemory)?,
+            ParameterMode::Relative => relative_address(&self.relative_base, &pos)?,
+        };
+        self.check_address(address)?;
+        let write = MemoryWrite {
+            address,
+            old: self.memory.get(address),
+            new: value.clone(),
+        };
+        if let Some(code_map) = &mut self.code_map {
+            let running = step.address..self.instruction_pointer;
+            if self.self_modification == SelfModification::Deny
+                && code_map.is_code(&running, address)
+            {
+                return Err(ComputerError::CodeModified(address));
+            }
+            code_map.write(&running, write.clone());
+        }
+        self.decoded.invalidate(address);
+        self.compiler.invalidate(address);
+        *self.memory.get_mut(address) = value;
+        step.write = Some(write);
+        Ok(())
+    }
+
+    pub fn base_memory(&self) -> &[V] {
+        self.memory.base()
+    }
+}
+
+#[futures_await_test::async_test]
+async fn test_step() {
+    let program = assemble("in [7]\nrbo [7]\nout rb+0\nhlt").unwrap();
+    let mut input: &[Value] = &[3];
+    let mut computer = Computer::load(program);
+    computer.set_input(Some(&mut input));
+
+    let step = computer.step().await.unwrap();
+    assert_eq!((step.op_code, step.input), (OpCode::Input, Some(3)));
+    assert_eq!(computer.instruction_pointer(), 2);
+
+    let step = computer.step().await.unwrap();
+    assert_eq!(step.op_code, OpCode::RelativeBaseOffset);
+    assert_eq!(computer.relative_base(), 3);
+
+    let step = computer.step().await.unwrap();
+    assert_eq!((step.address, step.output), (4, Some(7)));
+
+    for _ in 0..2 {
+        let step = computer.step().await.unwrap();
+        assert!(step.halted());
+        assert_eq!(computer.instruction_pointer(), 6);
+        assert_eq!(computer.executed_instructions(), 4);
+    }
+}
+
+#[test]
+fn test_resume() {
+    // Outputs the sum of every pair of inputs, until a pair sums to zero
+    let program = assemble(
+        "
+        loop:   in [a]
+                in [b]
+                add [a], [b], [a]
+                out [a]
+                jt [a], #loop
+                hlt
+        a:      .data 0
+        b:      .data 0
+        ",
+    )
+    .unwrap();
+    let mut computer = Computer::load(program);
+    let mut sums = Vec::new();
+    let mut inputs = vec![(1, 2), (3, 4), (5, -5)].into_iter();
+    loop {
+        match computer.resume().unwrap() {
+            Status::NeedsInput => {
+                let (a, b) = inputs.next().unwrap();
+                computer.provide_input(a);
+                computer.provide_input(b);
             }
-            ParameterMode::Relative => {
-                let pos = usize::try_from(self.relative_base + pos)
-                    .map_err(|_| ComputerError::ReadOutsideOfMemory)?;
-                Ok(self.memory.get_mut(pos))
+            Status::Output(value) => sums.push(value),
+            Status::Halted => break,
+        }
+    }
+    assert_eq!(sums, vec![3, 7, 0]);
+    let executed = computer.executed_instructions();
+    assert_eq!(computer.resume().unwrap(), Status::Halted);
+    assert_eq!(computer.executed_instructions(), executed);
+}
+
+#[test]
+fn test_limits() {
+    let limits = Limits {
+        instructions: Some(100),
+        memory: Some(1000),
+        outputs: Some(3),
+    };
+    let run = |program| {
+        let mut computer = Computer::load(parse_program(program).unwrap());
+        computer.set_limits(limits);
+        loop {
+            match computer.resume().map_err(|error| error.kind().clone())? {
+                Status::Halted => return Ok(computer.executed_instructions()),
+                Status::NeedsInput => computer.provide_input(0),
+                Status::Output(_) => {}
             }
         }
+    };
+    assert_eq!(run("1101,1,1,0,99"), Ok(2));
+    // An endless loop
+    assert_eq!(
+        run("1105,1,0"),
+        Err(ComputerError::InstructionLimitExceeded(100))
+    );
+    assert_eq!(
+        run("1101,1,1,1000,99"),
+        Err(ComputerError::MemoryLimitExceeded(1000))
+    );
+    assert_eq!(
+        run("4,0,1105,1,0"),
+        Err(ComputerError::OutputLimitExceeded(3))
+    );
+}
+
+#[test]
+fn test_relative_base_overflow() {
+    // Each program with the address of the instruction that overflows
+    let programs = vec![
+        (format!("109,{},109,1,99", Value::MAX), 2),
+        (format!("109,{},204,1,99", Value::MAX), 2),
+        // Overflow in a loop, once it is hot enough to be compiled
+        (format!("109,{},1105,1,0", Value::MAX / 4 + 1), 0),
+        (format!("21101,0,0,9,109,{},1105,1,0", Value::MAX), 0),
+    ];
+    for (program, address) in programs {
+        for &engine in &[Engine::Interpreter, Engine::Decoded, Engine::Compiled] {
+            let mut computer = Computer::load(parse_program(&program).unwrap());
+            computer.set_engine(engine);
+            let error = computer.resume().unwrap_err();
+            assert_eq!(error.kind(), &ComputerError::ArithmaticError);
+            assert_eq!(error.context().unwrap().instruction_pointer, address);
+        }
     }
+}
+
+#[test]
+fn test_error_context() {
+    // Reads from a negative address through the relative base
+    let mut computer = Computer::load(parse_program("109,-10,1201,0,3,0,99").unwrap());
+    let error = computer.resume().unwrap_err();
+    assert_eq!(error.kind(), &ComputerError::ReadOutsideOfMemory);
+    let context = error.context().unwrap();
+    assert_eq!(
+        (context.instruction_pointer, context.instruction),
+        (2, 1201)
+    );
+    assert_eq!(computer.instruction_pointer(), 2);
+    assert_eq!(
+        error.to_string(),
+        "attempted to read outside of memory (at address 2: instruction 1201, \
+         relative base -10, parameters rb+0, #3, [0])"
+    );
+    assert_eq!(
+        error.report(),
+        "error: attempted to read outside of memory\n\
+         at address 2, instruction 1201, relative base -10\n\
+         \x20     0: rbo #-10\n\
+         >     2: add rb+0, #3, [0]\n\
+         \x20     6: hlt\n"
+    );
+    // Only the end of a long program is decoded for the excerpt
+    let program = format!("{}98", "1101,0,0,0,".repeat(100));
+    let error = Computer::load(parse_program(&program).unwrap())
+        .resume()
+        .unwrap_err();
+    let addresses: Vec<_> = error
+        .context()
+        .unwrap()
+        .excerpt
+        .iter()
+        .map(Item::address)
+        .collect();
+    assert_eq!(addresses, vec![388, 392, 396, 400]);
+    assert_eq!(
+        parse_program("1,2,x"),
+        Err(ComputerError::ParseProgramError {
+            index: 2,
+            text: "x".to_string()
+        })
+    );
+}
 
-    pub fn base_memory(&self) -> &[Value] {
-        &self.memory.base
+#[test]
+fn test_value_width() {
+    // Multiplies 2^62 by 4, which does not fit in an i64
+    let mut computer =
+        Computer::load(parse_program("1102,4611686018427387904,4,7,4,7,99,0").unwrap());
+    let result = computer.resume().map_err(|error| error.kind().clone());
+    if cfg!(feature = "i128") {
+        assert_eq!(
+            result,
+            Ok(Status::Output("18446744073709551616".parse().unwrap()))
+        );
+    } else {
+        assert_eq!(result, Err(ComputerError::ArithmaticError));
     }
 }
+
+#[cfg(feature = "bigint")]
+#[test]
+fn test_big_integers() {
+    use num_bigint::BigInt;
+    // Multiplies 2^62 by 4, then compares the product with 2^64
+    let program: Memory<BigInt> =
+        "1102,4611686018427387904,4,13,4,13,8,13,14,15,4,15,99,0,18446744073709551616,0"
+            .parse()
+            .unwrap();
+    let mut computer = Computer::load(program);
+    let power: BigInt = "18446744073709551616".parse().unwrap();
+    assert_eq!(computer.resume(), Ok(Status::Output(power)));
+    assert_eq!(computer.resume(), Ok(Status::Output(BigInt::from(1))));
+    assert_eq!(computer.resume(), Ok(Status::Halted));
+    assert_eq!(
+        "1,x".parse::<Memory<BigInt>>(),
+        Err(ComputerError::ParseProgramError {
+            index: 1,
+            text: "x".to_string()
+        })
+    );
+}
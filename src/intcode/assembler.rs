@@ -0,0 +1,487 @@
+//! An assembler for the syntax produced by the disassembler.
+//!
+//! Every line contains at most one statement and may start with any number of `label:`
+//! definitions. Comments start with `;`. A leading `<address>:` as printed by the disassembler is
+//! ignored, so a disassembly can be edited and assembled again.
+//!
+//! ```text
+//!         add #3, #0, [counter]
+//! loop:   out [counter]
+//!         add [counter], #-1, [counter]
+//!         jt [counter], #loop
+//!         hlt
+//! counter: .data 0
+//! ```
+//!
+//! Operands are written as `[address]` for position mode, `#value` for immediate mode and
+//! `rb+offset` or `rb-offset` for relative mode. Addresses, values and offsets are expressions:
+//! numbers and labels combined with `+` and `-`.
+
+use crate::intcode::{Instruction, Memory, OpCode, ParameterMode, Value};
+use nom::bytes::complete::{tag, take_while1};
+use nom::character::complete::{char, digit1, space0};
+use nom::combinator::{map_res, recognize, verify};
+use nom::sequence::{pair, terminated};
+use nom::IResult;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use thiserror::*;
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum AssembleErrorKind {
+    #[error("expected {0}")]
+    Expected(&'static str),
+    #[error("unknown mnemonic: {0}")]
+    UnknownMnemonic(String),
+    #[error("unknown directive: {0}")]
+    UnknownDirective(String),
+    #[error("{mnemonic} takes {expected} operands, but {found} were given")]
+    OperandCount {
+        mnemonic: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    #[error("the label {0} is defined more than once")]
+    DuplicateLabel(String),
+    #[error("undefined label: {0}")]
+    UndefinedLabel(String),
+    #[error("the value does not fit in a memory cell")]
+    OutOfRange,
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("{line}:{column}: {kind}")]
+pub struct AssembleError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: AssembleErrorKind,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Term<'a> {
+    Number(Value),
+    Label(&'a str),
+}
+
+/// A sum of terms, each of which may be negated.
+#[derive(Clone, Debug, Default)]
+struct Expression<'a> {
+    terms: Vec<(bool, Term<'a>)>,
+}
+
+#[derive(Clone, Debug)]
+enum Statement<'a> {
+    Instruction {
+        op_code: OpCode,
+        operands: Vec<(ParameterMode, Expression<'a>)>,
+    },
+    Data(Vec<Expression<'a>>),
+}
+
+impl Statement<'_> {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction { operands, .. } => 1 + operands.len(),
+            Statement::Data(values) => values.len(),
+        }
+    }
+}
+
+/// A single line of source, used to turn positions within it into errors.
+struct Line<'a> {
+    text: &'a str,
+    number: usize,
+}
+
+impl<'a> Line<'a> {
+    fn error(&self, at: &str, kind: AssembleErrorKind) -> AssembleError {
+        AssembleError {
+            line: self.number,
+            column: at.as_ptr() as usize - self.text.as_ptr() as usize + 1,
+            kind,
+        }
+    }
+
+    fn expected(&self, at: &str, what: &'static str) -> AssembleError {
+        self.error(at, AssembleErrorKind::Expected(what))
+    }
+
+    /// Parse the line into its label definitions and an optional statement.
+    fn parse(&self) -> Result<(Vec<&'a str>, Option<Statement<'a>>), AssembleError> {
+        let text = self.text.split(';').next().unwrap_or_default();
+        let (mut rest, _) = skip_space(text);
+
+        // An address annotation from the disassembler
+        if let Ok((after, _)) = address_annotation(rest) {
+            rest = skip_space(after).0;
+        }
+
+        let mut labels = Vec::new();
+        while let Ok((after, label)) = label_definition(rest) {
+            labels.push(label);
+            rest = skip_space(after).0;
+        }
+
+        if rest.is_empty() {
+            return Ok((labels, None));
+        }
+        let (rest, statement) = if rest.starts_with('.') {
+            self.parse_directive(rest)?
+        } else {
+            self.parse_instruction(rest)?
+        };
+        let (rest, _) = skip_space(rest);
+        if !rest.is_empty() {
+            return Err(self.expected(rest, "end of line"));
+        }
+        Ok((labels, Some(statement)))
+    }
+
+    fn parse_directive(&self, input: &'a str) -> Result<(&'a str, Statement<'a>), AssembleError> {
+        let (rest, name) =
+            identifier(&input[1..]).map_err(|_| self.expected(input, "directive"))?;
+        match name {
+            "data" => {
+                let (rest, values) = self.parse_list(rest, |input| self.parse_expression(input))?;
+                if values.is_empty() {
+                    return Err(self.expected(rest, "value"));
+                }
+                Ok((rest, Statement::Data(values)))
+            }
+            other => Err(self.error(
+                input,
+                AssembleErrorKind::UnknownDirective(other.to_string()),
+            )),
+        }
+    }
+
+    fn parse_instruction(&self, input: &'a str) -> Result<(&'a str, Statement<'a>), AssembleError> {
+        let (rest, mnemonic) = identifier(input).map_err(|_| self.expected(input, "mnemonic"))?;
+        let op_code = op_code_from_mnemonic(mnemonic).ok_or_else(|| {
+            self.error(
+                input,
+                AssembleErrorKind::UnknownMnemonic(mnemonic.to_string()),
+            )
+        })?;
+        let (rest, operands) = self.parse_list(rest, |input| self.parse_operand(input))?;
+        if operands.len() != op_code.parameter_count() {
+            return Err(self.error(
+                input,
+                AssembleErrorKind::OperandCount {
+                    mnemonic: op_code.mnemonic(),
+                    expected: op_code.parameter_count(),
+                    found: operands.len(),
+                },
+            ));
+        }
+        Ok((rest, Statement::Instruction { op_code, operands }))
+    }
+
+    /// Parse a possibly empty, comma separated list.
+    fn parse_list<T>(
+        &self,
+        input: &'a str,
+        mut parse_item: impl FnMut(&'a str) -> Result<(&'a str, T), AssembleError>,
+    ) -> Result<(&'a str, Vec<T>), AssembleError> {
+        let mut items = Vec::new();
+        let (mut rest, _) = skip_space(input);
+        if rest.is_empty() {
+            return Ok((rest, items));
+        }
+        loop {
+            let (after, item) = parse_item(rest)?;
+            items.push(item);
+            let (after, _) = skip_space(after);
+            match comma(after) {
+                Ok((after, _)) => rest = skip_space(after).0,
+                Err(_) => return Ok((after, items)),
+            }
+        }
+    }
+
+    fn parse_operand(
+        &self,
+        input: &'a str,
+    ) -> Result<(&'a str, (ParameterMode, Expression<'a>)), AssembleError> {
+        if let Some(rest) = strip_prefix(input, "[") {
+            let (rest, expression) = self.parse_expression(skip_space(rest).0)?;
+            let (rest, _) = skip_space(rest);
+            let rest = strip_prefix(rest, "]").ok_or_else(|| self.expected(rest, "`]`"))?;
+            Ok((rest, (ParameterMode::Position, expression)))
+        } else if let Some(rest) = strip_prefix(input, "#") {
+            let (rest, expression) = self.parse_expression(rest)?;
+            Ok((rest, (ParameterMode::Immediate, expression)))
+        } else if let Ok((rest, "rb")) = identifier(input) {
+            let (after_space, _) = skip_space(rest);
+            if after_space.starts_with('+') || after_space.starts_with('-') {
+                let (rest, expression) = self.parse_expression(after_space)?;
+                Ok((rest, (ParameterMode::Relative, expression)))
+            } else {
+                Ok((rest, (ParameterMode::Relative, Expression::default())))
+            }
+        } else {
+            Err(self.expected(input, "operand"))
+        }
+    }
+
+    fn parse_expression(&self, input: &'a str) -> Result<(&'a str, Expression<'a>), AssembleError> {
+        let mut expression = Expression::default();
+        let mut rest = input;
+        let mut first = true;
+        loop {
+            let (after, _) = skip_space(rest);
+            if let Ok((after, value)) = negative_number(after) {
+                expression.terms.push((false, Term::Number(value)));
+                rest = after;
+                first = false;
+                continue;
+            }
+            let (after, negative) = if let Some(after) = strip_prefix(after, "-") {
+                (after, true)
+            } else if let Some(after) = strip_prefix(after, "+") {
+                (after, false)
+            } else if first {
+                (after, false)
+            } else {
+                return Ok((rest, expression));
+            };
+            let (after, _) = skip_space(after);
+            let (after, term) = term(after).map_err(|_| self.expected(after, "number or label"))?;
+            expression.terms.push((negative, term));
+            rest = after;
+            first = false;
+        }
+    }
+}
+
+fn skip_space(input: &str) -> (&str, &str) {
+    let result: IResult<&str, &str> = space0(input);
+    result.unwrap_or((input, ""))
+}
+
+fn comma(input: &str) -> IResult<&str, char> {
+    char(',')(input)
+}
+
+fn address_annotation(input: &str) -> IResult<&str, &str> {
+    terminated(digit1, pair(space0, char(':')))(input)
+}
+
+fn label_definition(input: &str) -> IResult<&str, &str> {
+    terminated(identifier, pair(space0, char(':')))(input)
+}
+
+fn strip_prefix<'a>(input: &'a str, prefix: &str) -> Option<&'a str> {
+    let result: IResult<&str, &str> = tag(prefix)(input);
+    result.ok().map(|(rest, _)| rest)
+}
+
+fn identifier(input: &str) -> IResult<&str, &str> {
+    verify(
+        take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+        |s: &str| !s.starts_with(|c: char| c.is_ascii_digit()),
+    )(input)
+}
+
+fn number(input: &str) -> IResult<&str, Value> {
+    map_res(recognize(digit1), Value::from_str)(input)
+}
+
+/// A minus sign directly followed by digits, parsed together so the minimum value fits.
+fn negative_number(input: &str) -> IResult<&str, Value> {
+    map_res(recognize(pair(char('-'), digit1)), Value::from_str)(input)
+}
+
+fn term(input: &str) -> IResult<&str, Term<'_>> {
+    if let Ok((rest, value)) = number(input) {
+        Ok((rest, Term::Number(value)))
+    } else {
+        let (rest, label) = identifier(input)?;
+        Ok((rest, Term::Label(label)))
+    }
+}
+
+fn op_code_from_mnemonic(mnemonic: &str) -> Option<OpCode> {
+    use OpCode::*;
+    [
+        Add,
+        Multiply,
+        Input,
+        Output,
+        JumpIfTrue,
+        JumpIfFalse,
+        LessThan,
+        Equals,
+        RelativeBaseOffset,
+        Quit,
+    ]
+    .iter()
+    .copied()
+    .find(|op_code| op_code.mnemonic() == mnemonic)
+}
+
+/// Evaluate an expression once all labels are known.
+fn evaluate(
+    line: &Line<'_>,
+    expression: &Expression<'_>,
+    labels: &HashMap<&str, usize>,
+) -> Result<Value, AssembleError> {
+    expression
+        .terms
+        .iter()
+        .try_fold(0 as Value, |total, (negative, term)| {
+            let value = match term {
+                Term::Number(value) => *value,
+                Term::Label(label) => {
+                    let address = *labels.get(label).ok_or_else(|| {
+                        line.error(label, AssembleErrorKind::UndefinedLabel(label.to_string()))
+                    })?;
+                    Value::try_from(address)
+                        .map_err(|_| line.error(label, AssembleErrorKind::OutOfRange))?
+                }
+            };
+            let result = if *negative {
+                total.checked_sub(value)
+            } else {
+                total.checked_add(value)
+            };
+            result.ok_or_else(|| line.error(line.text, AssembleErrorKind::OutOfRange))
+        })
+}
+
+/// Assemble a program into memory that can be loaded into a `Computer`.
+pub fn assemble(source: &str) -> Result<Memory, AssembleError> {
+    // First pass: parse every line and assign addresses to labels.
+    let mut statements = Vec::new();
+    let mut labels = HashMap::new();
+    let mut address = 0;
+    for (number, text) in source.lines().enumerate() {
+        let line = Line {
+            text,
+            number: number + 1,
+        };
+        let (line_labels, statement) = line.parse()?;
+        for label in line_labels {
+            if labels.insert(label, address).is_some() {
+                return Err(line.error(label, AssembleErrorKind::DuplicateLabel(label.to_string())));
+            }
+        }
+        if let Some(statement) = statement {
+            address += statement.size();
+            statements.push((line, statement));
+        }
+    }
+
+    // Second pass: resolve all expressions.
+    let mut image = Vec::with_capacity(address);
+    for (line, statement) in &statements {
+        match statement {
+            Statement::Instruction { op_code, operands } => {
+                image.push(Instruction::encode(*op_code, operands.iter().map(|(mode, _)| *mode)).0);
+                for (_, expression) in operands {
+                    image.push(evaluate(line, expression, &labels)?);
+                }
+            }
+            Statement::Data(values) => {
+                for expression in values {
+                    image.push(evaluate(line, expression, &labels)?);
+                }
+            }
+        }
+    }
+    Ok(Memory::from(image))
+}
+
+#[test]
+fn test_assemble() {
+    let source = "
+        ; count down from 3
+                add #3, #0, [counter]
+        loop:   out [counter]
+                add [counter], #-1, [counter]
+                jt [counter], #loop
+                hlt
+        counter: .data 0
+    ";
+    assert_eq!(
+        assemble(source).unwrap().base(),
+        &[1101, 3, 0, 14, 4, 14, 1001, 14, -1, 14, 1005, 14, 4, 99, 0]
+    );
+}
+
+#[test]
+fn test_assemble_relative() {
+    let memory = assemble("rbo #end\nadd rb, rb+1, rb-1\nend: hlt").unwrap();
+    assert_eq!(memory.base(), &[109, 6, 22201, 0, 1, -1, 99]);
+}
+
+#[test]
+fn test_assemble_errors() {
+    let error = |source| assemble(source).unwrap_err();
+    assert_eq!(
+        error("hlt\n  foo [1]"),
+        AssembleError {
+            line: 2,
+            column: 3,
+            kind: AssembleErrorKind::UnknownMnemonic("foo".to_string())
+        }
+    );
+    assert_eq!(
+        error("out [1"),
+        AssembleError {
+            line: 1,
+            column: 7,
+            kind: AssembleErrorKind::Expected("`]`")
+        }
+    );
+    assert_eq!(
+        error("jt #1, #nowhere"),
+        AssembleError {
+            line: 1,
+            column: 9,
+            kind: AssembleErrorKind::UndefinedLabel("nowhere".to_string())
+        }
+    );
+    assert_eq!(
+        error("a: hlt\na: hlt"),
+        AssembleError {
+            line: 2,
+            column: 1,
+            kind: AssembleErrorKind::DuplicateLabel("a".to_string())
+        }
+    );
+    assert_eq!(
+        error("add #1, #2"),
+        AssembleError {
+            line: 1,
+            column: 1,
+            kind: AssembleErrorKind::OperandCount {
+                mnemonic: "add",
+                expected: 3,
+                found: 2
+            }
+        }
+    );
+}
+
+#[test]
+fn test_round_trip() {
+    use crate::intcode::{disassemble, parse_program};
+    use std::fs::File;
+    use std::io::Read;
+    for day in &["day5", "day9", "day13", "day15"] {
+        let mut s = String::new();
+        File::open(format!("./input/{}", day))
+            .unwrap()
+            .read_to_string(&mut s)
+            .unwrap();
+        let memory = parse_program(&s).unwrap();
+        let source = disassemble(&memory).to_string();
+        assert_eq!(assemble(&source).unwrap().base(), memory.base());
+    }
+    let memory = Memory::from(vec![Value::MIN, Value::MAX]);
+    let source = disassemble(&memory).to_string();
+    assert_eq!(assemble(&source).unwrap().base(), memory.base());
+    assert_eq!(assemble(".data 1-2, 1 - 2").unwrap().base(), &[-1, -1]);
+}
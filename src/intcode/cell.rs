@@ -0,0 +1,99 @@
+//! The types a `Computer` can keep in its memory.
+//!
+//! `Value` is used everywhere by default. `i64` and `i128` give the same results on every target,
+//! and with the `bigint` feature, `num_bigint::BigInt` never overflows at all.
+
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::str::FromStr;
+
+/// The value of a memory cell, which defaults to zero.
+pub trait Cell:
+    Clone + Eq + Ord + Hash + Default + Debug + Display + FromStr + From<i64> + Send + Sync + 'static
+{
+    /// The sum, or `None` if it does not fit.
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+
+    /// The product, or `None` if it does not fit.
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    /// The quotient and remainder of a division that rounds towards zero, like `/` and `%`.
+    fn div_rem(&self, divisor: i64) -> (Self, i64);
+
+    fn to_i64(&self) -> Option<i64>;
+
+    fn to_usize(&self) -> Option<usize>;
+}
+
+impl Cell for i64 {
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i64::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i64::checked_mul(*self, *other)
+    }
+
+    fn div_rem(&self, divisor: i64) -> (Self, i64) {
+        (self / divisor, self % divisor)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        usize::try_from(*self).ok()
+    }
+}
+
+impl Cell for i128 {
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i128::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i128::checked_mul(*self, *other)
+    }
+
+    fn div_rem(&self, divisor: i64) -> (Self, i64) {
+        let divisor = i128::from(divisor);
+        // The remainder is smaller than the divisor
+        (self / divisor, (self % divisor) as i64)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        i64::try_from(*self).ok()
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        usize::try_from(*self).ok()
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl Cell for num_bigint::BigInt {
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn div_rem(&self, divisor: i64) -> (Self, i64) {
+        let divisor = num_bigint::BigInt::from(divisor);
+        // The remainder is smaller than the divisor
+        let remainder = Cell::to_i64(&(self % &divisor)).unwrap();
+        (self / divisor, remainder)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        num_traits::ToPrimitive::to_i64(self)
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        num_traits::ToPrimitive::to_usize(self)
+    }
+}
@@ -0,0 +1,480 @@
+//! Static control-flow analysis of Intcode programs.
+//!
+//! Code is discovered by following jumps from address zero, so anything only reached through a
+//! computed jump is not part of the graph. Calls are recognized by the convention the puzzle
+//! programs use: the caller stores the return address at `rb+n` and jumps to the function, which
+//! adjusts the relative base and returns with an unconditional jump to `rb+0`.
+
+use crate::intcode::disassembler::decode;
+use crate::intcode::{Item, Memory, OpCode, ParameterMode, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// The destination of a jump.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Target {
+    Address(usize),
+    /// The address is read from memory when the jump executes.
+    Computed,
+}
+
+/// How control leaves a basic block.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Exit {
+    /// Continues with the block that starts right after it.
+    Fallthrough(usize),
+    Jump(Target),
+    Branch {
+        taken: Target,
+        not_taken: usize,
+    },
+    /// Calls a function, which returns to `return_to`.
+    Call {
+        function: usize,
+        return_to: usize,
+    },
+    Return,
+    Halt,
+    /// Runs into a value that is not a valid instruction, or jumps to a negative address.
+    Invalid,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// The address just after the last instruction.
+    pub end: usize,
+    pub instructions: Vec<Item>,
+    pub exit: Exit,
+}
+
+impl BasicBlock {
+    /// The blocks control can continue with within the same function.
+    pub fn successors(&self) -> Vec<usize> {
+        match self.exit {
+            Exit::Fallthrough(next) | Exit::Jump(Target::Address(next)) => vec![next],
+            Exit::Branch {
+                taken: Target::Address(taken),
+                not_taken,
+            } => vec![taken, not_taken],
+            Exit::Branch { not_taken, .. } => vec![not_taken],
+            Exit::Call { return_to, .. } => vec![return_to],
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Function {
+    pub entry: usize,
+    /// The relative base offset at the entry, if the function starts by allocating a frame.
+    pub frame_size: Option<Value>,
+    /// The start addresses of the blocks that belong to the function, in order.
+    pub blocks: Vec<usize>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct ControlFlowGraph {
+    blocks: BTreeMap<usize, BasicBlock>,
+    functions: Vec<Function>,
+}
+
+impl ControlFlowGraph {
+    /// The blocks, in order of address.
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    pub fn block(&self, start: usize) -> Option<&BasicBlock> {
+        self.blocks.get(&start)
+    }
+
+    /// The entry point at address zero, followed by every called function in order of address.
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
+    /// The addresses of jumps to computed addresses, other than returns.
+    pub fn computed_jumps(&self) -> Vec<usize> {
+        self.blocks()
+            .filter(|block| {
+                matches!(
+                    block.exit,
+                    Exit::Jump(Target::Computed)
+                        | Exit::Branch {
+                            taken: Target::Computed,
+                            ..
+                        }
+                )
+            })
+            .filter_map(|block| block.instructions.last().map(Item::address))
+            .collect()
+    }
+
+    /// The graph in Graphviz DOT format, with a cluster for every function.
+    pub fn to_dot(&self) -> Dot<'_> {
+        Dot { graph: self }
+    }
+}
+
+/// Find the code reachable from address zero and split it into basic blocks and functions.
+pub fn analyze(memory: &Memory) -> ControlFlowGraph {
+    let mut instructions = BTreeMap::new();
+    let mut exits = BTreeMap::new();
+    let mut leaders = BTreeSet::new();
+    let mut functions = BTreeSet::new();
+    leaders.insert(0);
+    functions.insert(0);
+    let mut stack = vec![0];
+    while let Some(address) = stack.pop() {
+        if instructions.contains_key(&address) {
+            continue;
+        }
+        let item = decode(memory, address);
+        if let Item::Data { .. } = item {
+            continue;
+        }
+        let next = address + item.size();
+        let exit = exit_of(&item, &instructions);
+        instructions.insert(address, item);
+        let mut targets = Vec::new();
+        match exit {
+            None => stack.push(next),
+            Some(Exit::Jump(Target::Address(to))) => targets.push(to),
+            Some(Exit::Branch { taken, not_taken }) => {
+                if let Target::Address(taken) = taken {
+                    targets.push(taken);
+                }
+                targets.push(not_taken);
+            }
+            Some(Exit::Call {
+                function,
+                return_to,
+            }) => {
+                functions.insert(function);
+                targets.push(function);
+                targets.push(return_to);
+            }
+            Some(_) => {}
+        }
+        for target in targets {
+            leaders.insert(target);
+            stack.push(target);
+        }
+        if let Some(exit) = exit {
+            exits.insert(address, exit);
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    for &start in &leaders {
+        let mut block = BasicBlock {
+            start,
+            end: start,
+            instructions: Vec::new(),
+            exit: Exit::Invalid,
+        };
+        while let Some(item) = instructions.get(&block.end) {
+            let address = block.end;
+            block.instructions.push(item.clone());
+            block.end += item.size();
+            if let Some(exit) = exits.get(&address) {
+                block.exit = *exit;
+                break;
+            } else if leaders.contains(&block.end) {
+                block.exit = Exit::Fallthrough(block.end);
+                break;
+            }
+        }
+        blocks.insert(start, block);
+    }
+
+    let functions = functions
+        .into_iter()
+        .map(|entry| function(&blocks, entry))
+        .collect();
+    ControlFlowGraph { blocks, functions }
+}
+
+/// How an instruction transfers control, or `None` if it just continues with the next one.
+fn exit_of(item: &Item, instructions: &BTreeMap<usize, Item>) -> Option<Exit> {
+    let (address, op_code, parameters) = match item {
+        Item::Instruction {
+            address,
+            op_code,
+            parameters,
+        } => (*address, *op_code, parameters),
+        Item::Data { .. } => return Some(Exit::Invalid),
+    };
+    let jump_if = match op_code {
+        OpCode::Quit => return Some(Exit::Halt),
+        OpCode::JumpIfTrue => true,
+        OpCode::JumpIfFalse => false,
+        _ => return None,
+    };
+    let next = address + item.size();
+    let (condition, to) = (parameters[0], parameters[1]);
+    let always = match condition.mode {
+        ParameterMode::Immediate if (condition.value != 0) == jump_if => true,
+        ParameterMode::Immediate => return None,
+        _ => false,
+    };
+    let target = match to.mode {
+        ParameterMode::Immediate => match usize::try_from(to.value) {
+            Ok(to) => Target::Address(to),
+            Err(_) => return Some(Exit::Invalid),
+        },
+        ParameterMode::Relative if always && to.value == 0 => return Some(Exit::Return),
+        _ => Target::Computed,
+    };
+    Some(match (always, target) {
+        (true, Target::Address(function)) if stores_return_address(instructions, address, next) => {
+            Exit::Call {
+                function,
+                return_to: next,
+            }
+        }
+        (true, target) => Exit::Jump(target),
+        (false, taken) => Exit::Branch {
+            taken,
+            not_taken: next,
+        },
+    })
+}
+
+/// Whether the straight-line code leading up to `address` stores `return_to` on the stack.
+fn stores_return_address(
+    instructions: &BTreeMap<usize, Item>,
+    address: usize,
+    return_to: usize,
+) -> bool {
+    let return_to = return_to as Value;
+    let mut end = address;
+    while let Some((_, item)) = instructions.range(..end).next_back() {
+        let (op_code, parameters) = match item {
+            Item::Instruction {
+                op_code,
+                parameters,
+                ..
+            } if item.address() + item.size() == end => (*op_code, parameters),
+            _ => return false,
+        };
+        let identity = match op_code {
+            OpCode::Add => 0,
+            OpCode::Multiply => 1,
+            _ => return false,
+        };
+        let is_immediate = |i: usize, value| {
+            parameters[i].mode == ParameterMode::Immediate && parameters[i].value == value
+        };
+        if parameters[2].mode == ParameterMode::Relative
+            && ((is_immediate(0, return_to) && is_immediate(1, identity))
+                || (is_immediate(0, identity) && is_immediate(1, return_to)))
+        {
+            return true;
+        }
+        end = item.address();
+    }
+    false
+}
+
+fn function(blocks: &BTreeMap<usize, BasicBlock>, entry: usize) -> Function {
+    let frame_size = blocks
+        .get(&entry)
+        .and_then(|block| block.instructions.first())
+        .and_then(|item| match item {
+            Item::Instruction {
+                op_code: OpCode::RelativeBaseOffset,
+                parameters,
+                ..
+            } if parameters[0].mode == ParameterMode::Immediate && parameters[0].value > 0 => {
+                Some(parameters[0].value)
+            }
+            _ => None,
+        });
+    let mut visited = BTreeSet::new();
+    let mut stack = vec![entry];
+    while let Some(start) = stack.pop() {
+        if let Some(block) = blocks.get(&start) {
+            if visited.insert(start) {
+                stack.extend(block.successors());
+            }
+        }
+    }
+    Function {
+        entry,
+        frame_size,
+        blocks: visited.into_iter().collect(),
+    }
+}
+
+/// A control-flow graph in Graphviz DOT format.
+pub struct Dot<'a> {
+    graph: &'a ControlFlowGraph,
+}
+
+fn write_node(f: &mut fmt::Formatter<'_>, block: &BasicBlock) -> fmt::Result {
+    write!(f, "        b{} [label=\"", block.start)?;
+    for item in &block.instructions {
+        let line = format!("{:>5}: {}", item.address(), item);
+        write!(f, "{}\\l", line.replace('\\', "\\\\").replace('"', "\\\""))?;
+    }
+    if block.exit == Exit::Invalid {
+        write!(f, "{:>5}: invalid\\l", block.end)?;
+    }
+    write!(f, "\"")?;
+    match block.exit {
+        Exit::Jump(Target::Computed)
+        | Exit::Branch {
+            taken: Target::Computed,
+            ..
+        }
+        | Exit::Invalid => write!(f, ", color=red")?,
+        _ => {}
+    }
+    writeln!(f, "];")
+}
+
+impl fmt::Display for Dot<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "digraph intcode {{")?;
+        writeln!(f, "    node [shape=box, fontname=\"monospace\"];")?;
+        let mut drawn = BTreeSet::new();
+        for function in self.graph.functions() {
+            writeln!(f, "    subgraph cluster_{} {{", function.entry)?;
+            match function.frame_size {
+                Some(size) => writeln!(
+                    f,
+                    "        label=\"function {} (frame {})\";",
+                    function.entry, size
+                )?,
+                None => writeln!(f, "        label=\"function {}\";", function.entry)?,
+            }
+            for start in &function.blocks {
+                if drawn.insert(*start) {
+                    write_node(f, &self.graph.blocks[start])?;
+                }
+            }
+            writeln!(f, "    }}")?;
+        }
+        for block in self.graph.blocks() {
+            let from = block.start;
+            match block.exit {
+                Exit::Fallthrough(to) | Exit::Jump(Target::Address(to)) => {
+                    writeln!(f, "    b{} -> b{};", from, to)?
+                }
+                Exit::Branch { taken, not_taken } => {
+                    if let Target::Address(taken) = taken {
+                        writeln!(f, "    b{} -> b{} [label=\"taken\"];", from, taken)?;
+                    }
+                    writeln!(f, "    b{} -> b{} [label=\"not taken\"];", from, not_taken)?;
+                }
+                Exit::Call {
+                    function,
+                    return_to,
+                } => {
+                    writeln!(
+                        f,
+                        "    b{} -> b{} [label=\"call\", style=dashed];",
+                        from, function
+                    )?;
+                    writeln!(f, "    b{} -> b{} [style=dotted];", from, return_to)?;
+                }
+                _ => {}
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+#[test]
+fn test_analyze() {
+    use crate::intcode::assemble;
+    let program = assemble(
+        "
+                rbo #stack
+                in [n]
+                add #back, #0, rb+0
+                jt #1, #double
+        back:   out [n]
+                jf [n], #end
+                jt #1, [table]
+        end:    hlt
+        double: rbo #2
+                mul [n], #2, [n]
+                rbo #-2
+                jf #0, rb+0
+        n:      .data 0
+        table:  .data 0
+        stack:  .data 0, 0, 0
+        ",
+    )
+    .unwrap();
+    let graph = analyze(&program);
+    let exits: Vec<_> = graph
+        .blocks()
+        .map(|block| (block.start, block.exit))
+        .collect();
+    assert_eq!(
+        exits,
+        vec![
+            (
+                0,
+                Exit::Call {
+                    function: 20,
+                    return_to: 11
+                }
+            ),
+            (
+                11,
+                Exit::Branch {
+                    taken: Target::Address(19),
+                    not_taken: 16
+                }
+            ),
+            (16, Exit::Jump(Target::Computed)),
+            (19, Exit::Halt),
+            (20, Exit::Return),
+        ]
+    );
+    assert_eq!(graph.computed_jumps(), vec![16]);
+    assert_eq!(
+        graph.functions(),
+        &[
+            Function {
+                entry: 0,
+                frame_size: Some(33),
+                blocks: vec![0, 11, 16, 19],
+            },
+            Function {
+                entry: 20,
+                frame_size: Some(2),
+                blocks: vec![20],
+            },
+        ]
+    );
+    let dot = graph.to_dot().to_string();
+    assert!(dot.starts_with("digraph intcode {\n"));
+    assert!(dot.contains(
+        "        b20 [label=\"   20: rbo #2\\l   22: mul [31], #2, [31]\\l   26: rbo #-2\\l   28: jf #0, rb+0\\l\"];\n"
+    ));
+    assert!(dot.contains("    b0 -> b20 [label=\"call\", style=dashed];\n"));
+    assert!(dot.contains("    b11 -> b19 [label=\"taken\"];\n"));
+}
+
+#[test]
+fn test_analyze_day_13() {
+    use crate::intcode::parse_program;
+    use std::fs;
+    let program = parse_program(&fs::read_to_string("input/day13").unwrap()).unwrap();
+    let graph = analyze(&program);
+    assert!(graph.functions().len() > 1);
+    let main = &graph.functions()[0];
+    assert_eq!(main.frame_size, None);
+    assert!(graph
+        .functions()
+        .iter()
+        .any(|function| function.entry == 578 && function.frame_size == Some(3)));
+    assert!(graph.blocks().all(|block| block.exit != Exit::Invalid));
+}
@@ -0,0 +1,271 @@
+//! Tracking of the memory cells a program executes and writes, to find self-modifying code.
+
+use crate::intcode::{Cell, MemoryWrite, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+const EXECUTED: u8 = 1;
+const WRITTEN: u8 = 2;
+
+/// Whether a `Computer` watches for programs that write over their own code.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum SelfModification {
+    /// Nothing is tracked.
+    Allow,
+    /// Executed and written cells are tracked in a `CodeMap`, which lists writes to code.
+    Report,
+    /// Like `Report`, but writing to a cell that was executed fails with
+    /// `ComputerError::CodeModified`.
+    Deny,
+}
+
+impl Default for SelfModification {
+    fn default() -> Self {
+        SelfModification::Allow
+    }
+}
+
+/// A write to a cell that had been executed as part of an instruction.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct CodeWrite<V: Cell = Value> {
+    /// The address of the instruction that wrote to the code.
+    pub instruction: usize,
+    pub write: MemoryWrite<V>,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum RegionKind {
+    /// Only executed.
+    Code,
+    /// Only written.
+    Data,
+    /// Both executed and written, in either order.
+    Modified,
+}
+
+/// A run of cells that were used the same way.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Region {
+    pub kind: RegionKind,
+    pub cells: Range<usize>,
+}
+
+/// The cells a program has executed and written so far.
+#[derive(Clone, Debug, Default)]
+pub struct CodeMap<V: Cell = Value> {
+    cells: BTreeMap<usize, u8>,
+    code_writes: Vec<CodeWrite<V>>,
+}
+
+impl<V: Cell> CodeMap<V> {
+    pub(crate) fn execute(&mut self, cells: Range<usize>) {
+        for address in cells {
+            *self.cells.entry(address).or_insert(0) |= EXECUTED;
+        }
+    }
+
+    /// Whether writing to `address` changes code. The cells of the `running` instruction count
+    /// as code, as they are only recorded once it succeeds.
+    pub(crate) fn is_code(&self, running: &Range<usize>, address: usize) -> bool {
+        running.contains(&address) || self.is_executed(address)
+    }
+
+    /// Record a write by the `running` instruction that went through.
+    pub(crate) fn write(&mut self, running: &Range<usize>, write: MemoryWrite<V>) {
+        if self.is_code(running, write.address) {
+            self.code_writes.push(CodeWrite {
+                instruction: running.start,
+                write: write.clone(),
+            });
+        }
+        *self.cells.entry(write.address).or_insert(0) |= WRITTEN;
+    }
+
+    pub fn is_executed(&self, address: usize) -> bool {
+        self.cells.get(&address).map(|cell| cell & EXECUTED) == Some(EXECUTED)
+    }
+
+    pub fn is_written(&self, address: usize) -> bool {
+        self.cells.get(&address).map(|cell| cell & WRITTEN) == Some(WRITTEN)
+    }
+
+    /// The writes to cells that had been executed, in order.
+    pub fn code_writes(&self) -> &[CodeWrite<V>] {
+        &self.code_writes
+    }
+
+    /// The cells that were executed or written, grouped into regions.
+    pub fn regions(&self) -> Vec<Region> {
+        let mut regions: Vec<Region> = Vec::new();
+        for (&address, &cell) in &self.cells {
+            let kind = match cell {
+                EXECUTED => RegionKind::Code,
+                WRITTEN => RegionKind::Data,
+                _ => RegionKind::Modified,
+            };
+            match regions.last_mut() {
+                Some(last) if last.kind == kind && last.cells.end == address => {
+                    last.cells.end = address + 1
+                }
+                _ => regions.push(Region {
+                    kind,
+                    cells: address..address + 1,
+                }),
+            }
+        }
+        regions
+    }
+}
+
+/// A summary of the regions, followed by every write to code.
+impl<V: Cell> fmt::Display for CodeMap<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for region in self.regions() {
+            let kind = match region.kind {
+                RegionKind::Code => "code",
+                RegionKind::Data => "data",
+                RegionKind::Modified => "modified code",
+            };
+            writeln!(
+                f,
+                "{:>5}..{:<5} {}",
+                region.cells.start, region.cells.end, kind
+            )?;
+        }
+        for CodeWrite { instruction, write } in &self.code_writes {
+            writeln!(
+                f,
+                "{:>5}: wrote {} over {} at {}",
+                instruction, write.new, write.old, write.address
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_self_modification() {
+    use crate::intcode::{assemble, parse_program, Computer, ComputerError, Limits, Status};
+    use std::fs;
+    // Increments the parameter of its own output instruction
+    let program = assemble(
+        "
+        loop:   out #1
+                add [loop+1], #1, [loop+1]
+                add [count], #-1, [count]
+                jt [count], #loop
+                hlt
+        count:  .data 2
+        ",
+    )
+    .unwrap();
+    let mut computer = Computer::load(program.clone());
+    computer.set_self_modification(SelfModification::Report);
+    while computer.resume().unwrap() != Status::Halted {}
+    let code_map = computer.code_map().unwrap();
+    assert_eq!(
+        code_map.code_writes(),
+        &[
+            CodeWrite {
+                instruction: 2,
+                write: MemoryWrite {
+                    address: 1,
+                    old: 1,
+                    new: 2
+                }
+            },
+            CodeWrite {
+                instruction: 2,
+                write: MemoryWrite {
+                    address: 1,
+                    old: 2,
+                    new: 3
+                }
+            }
+        ]
+    );
+    assert_eq!(
+        code_map.regions(),
+        vec![
+            Region {
+                kind: RegionKind::Code,
+                cells: 0..1
+            },
+            Region {
+                kind: RegionKind::Modified,
+                cells: 1..2
+            },
+            Region {
+                kind: RegionKind::Code,
+                cells: 2..14
+            },
+            Region {
+                kind: RegionKind::Data,
+                cells: 14..15
+            },
+        ]
+    );
+    assert_eq!(
+        code_map.to_string(),
+        "    0..1     code\n    1..2     modified code\n    2..14    code\n   14..15    data\n    \
+         2: wrote 2 over 1 at 1\n    2: wrote 3 over 2 at 1\n"
+    );
+
+    // Restoring or stepping back starts the map over
+    let snapshot = computer.snapshot();
+    computer.restore(snapshot);
+    assert_eq!(computer.code_map().unwrap().regions(), vec![]);
+    let mut computer = Computer::load(program.clone());
+    computer.set_self_modification(SelfModification::Report);
+    computer.set_history(Some(Default::default()));
+    assert_eq!(computer.resume(), Ok(Status::Output(1)));
+    computer.reverse_step().unwrap();
+    assert!(!computer.code_map().unwrap().is_executed(0));
+    assert_eq!(computer.resume(), Ok(Status::Output(1)));
+    assert!(computer.code_map().unwrap().is_executed(0));
+
+    // A denied write is not recorded
+    let mut computer = Computer::load(program);
+    computer.set_self_modification(SelfModification::Deny);
+    assert_eq!(computer.resume(), Ok(Status::Output(1)));
+    let error = computer.resume().unwrap_err();
+    assert_eq!(error.kind(), &ComputerError::CodeModified(1));
+    assert_eq!(computer.instruction_pointer(), 2);
+    let code_map = computer.code_map().unwrap();
+    assert!(!code_map.is_written(1));
+    assert_eq!(code_map.code_writes(), &[]);
+
+    // A failing instruction is not recorded as executed, but one that writes over itself is
+    let mut computer = Computer::load(parse_program("1101,1,1,20,99").unwrap());
+    computer.set_self_modification(SelfModification::Deny);
+    computer.set_limits(Limits {
+        memory: Some(10),
+        ..Limits::default()
+    });
+    let error = computer.resume().unwrap_err();
+    assert_eq!(error.kind(), &ComputerError::MemoryLimitExceeded(10));
+    assert_eq!(computer.code_map().unwrap().regions(), vec![]);
+    let mut computer = Computer::load(parse_program("1101,0,0,3,99").unwrap());
+    computer.set_self_modification(SelfModification::Deny);
+    let error = computer.resume().unwrap_err();
+    assert_eq!(error.kind(), &ComputerError::CodeModified(3));
+
+    // The arcade cabinet only patches the parameters of its table lookups
+    let mut arcade = parse_program(&fs::read_to_string("input/day13").unwrap()).unwrap();
+    *arcade.get_mut(0) = 2;
+    let mut computer = Computer::load(arcade);
+    computer.set_self_modification(SelfModification::Report);
+    computer.provide_input(0);
+    while computer.resume().unwrap() != Status::NeedsInput {}
+    let mut patched: Vec<_> = computer
+        .code_map()
+        .unwrap()
+        .code_writes()
+        .iter()
+        .map(|code_write| code_write.write.address)
+        .collect();
+    patched.sort();
+    patched.dedup();
+    assert_eq!(patched, vec![566, 594]);
+}
@@ -0,0 +1,295 @@
+//! Translation of basic blocks into closures, for `Engine::Compiled`.
+//!
+//! A block is a run of arithmetic, comparison and relative base instructions, ending after the
+//! first jump or before the first instruction that does input or output, halts, or cannot be
+//! compiled. Those are left to the interpreter, as are blocks that have not been reached often
+//! enough to be worth compiling. When a program writes over compiled code, every block is dropped
+//! and the written cell is never compiled again, so self-modifying code falls back to the
+//! interpreter.
+
+use crate::intcode::{
+    relative_address, Cell, Computer, ComputerError, Engine, Limits, Memory, OpCode, ParameterMode,
+};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// What a compiled instruction did, besides reading memory.
+enum Effect {
+    Next,
+    Jump(usize),
+    Wrote(usize),
+}
+
+type Run<V> = Box<dyn Fn(&mut Memory<V>, &mut V) -> Result<Effect, ComputerError<V>>>;
+type Operand<V> = Box<dyn Fn(&Memory<V>, &V) -> Result<V, ComputerError<V>>>;
+type Target<V> = Box<dyn Fn(&V) -> Result<usize, ComputerError<V>>>;
+
+struct Operation<V: Cell> {
+    address: usize,
+    /// The address of the next instruction.
+    next: usize,
+    run: Run<V>,
+}
+
+/// The number of times a block has to be reached before it is compiled.
+const HOT_THRESHOLD: u8 = 2;
+
+struct Block<V: Cell> {
+    operations: Vec<Operation<V>>,
+    end: usize,
+}
+
+#[derive(Default)]
+pub(crate) struct Compiler<V: Cell> {
+    /// Blocks by start address, or `None` if nothing could be compiled there.
+    blocks: HashMap<usize, Option<Rc<Block<V>>>>,
+    /// The cells of the image that are part of a compiled block.
+    compiled: Vec<bool>,
+    /// The cells of the image that were written while they were part of a compiled block.
+    modified: Vec<bool>,
+    /// How often each address was reached without a block.
+    visits: Vec<u8>,
+}
+
+impl<V: Cell> Compiler<V> {
+    fn block(&mut self, memory: &Memory<V>, address: usize) -> Option<Rc<Block<V>>> {
+        let image = memory.base();
+        if self.compiled.len() != image.len() {
+            *self = Compiler {
+                blocks: HashMap::new(),
+                compiled: vec![false; image.len()],
+                modified: vec![false; image.len()],
+                visits: vec![0; image.len()],
+            };
+        }
+        if let Some(block) = self.blocks.get(&address) {
+            return block.clone();
+        }
+        match self.visits.get_mut(address) {
+            Some(visits) if *visits + 1 < HOT_THRESHOLD => {
+                *visits += 1;
+                return None;
+            }
+            None => return None,
+            Some(_) => {}
+        }
+        let block = compile(image, address, &self.modified).map(Rc::new);
+        if let Some(block) = &block {
+            for cell in &mut self.compiled[address..block.end] {
+                *cell = true;
+            }
+        }
+        self.blocks.insert(address, block.clone());
+        block
+    }
+
+    /// Drop every block if `address` is compiled code, returning whether it was.
+    pub fn invalidate(&mut self, address: usize) -> bool {
+        if !self.compiled.get(address).copied().unwrap_or(false) {
+            return false;
+        }
+        self.blocks.clear();
+        for cell in &mut self.compiled {
+            *cell = false;
+        }
+        self.modified[address] = true;
+        true
+    }
+
+    pub fn clear(&mut self) {
+        *self = Compiler::default();
+    }
+}
+
+fn compile<V: Cell>(image: &[V], start: usize, modified: &[bool]) -> Option<Block<V>> {
+    let mut operations = Vec::new();
+    let mut address = start;
+    while let Some((op_code, parameters)) = decode(image, address, modified) {
+        let run = match op_code {
+            OpCode::Add => binary(&parameters, |a, b| {
+                a.checked_add(b).ok_or(ComputerError::ArithmaticError)
+            }),
+            OpCode::Multiply => binary(&parameters, |a, b| {
+                a.checked_mul(b).ok_or(ComputerError::ArithmaticError)
+            }),
+            OpCode::LessThan => binary(&parameters, |a, b| Ok(V::from(i64::from(a < b)))),
+            OpCode::Equals => binary(&parameters, |a, b| Ok(V::from(i64::from(a == b)))),
+            OpCode::JumpIfTrue => jump(&parameters, true),
+            OpCode::JumpIfFalse => jump(&parameters, false),
+            OpCode::RelativeBaseOffset => relative_base_offset(&parameters),
+            OpCode::Input | OpCode::Output | OpCode::Quit => None,
+        };
+        let run = match run {
+            Some(run) => run,
+            None => break,
+        };
+        let next = address + 1 + parameters.len();
+        operations.push(Operation { address, next, run });
+        address = next;
+        if op_code == OpCode::JumpIfTrue || op_code == OpCode::JumpIfFalse {
+            break;
+        }
+    }
+    if operations.is_empty() {
+        None
+    } else {
+        Some(Block {
+            operations,
+            end: address,
+        })
+    }
+}
+
+/// Decode an instruction that lies within the image and was never modified.
+fn decode<V: Cell>(
+    image: &[V],
+    address: usize,
+    modified: &[bool],
+) -> Option<(OpCode, Vec<(ParameterMode, V)>)> {
+    let (mut modes, op_code) = image.get(address)?.div_rem(100);
+    let op_code = OpCode::from_code(op_code)?;
+    let end = address + 1 + op_code.parameter_count();
+    if end > image.len() || modified[address..end].contains(&true) {
+        return None;
+    }
+    let mut parameters = Vec::with_capacity(op_code.parameter_count());
+    for value in &image[address + 1..end] {
+        let (rest, mode) = modes.div_rem(10);
+        parameters.push((ParameterMode::from_code(mode)?, value.clone()));
+        modes = rest;
+    }
+    Some((op_code, parameters))
+}
+
+/// An operand to read. Negative addresses are left to the interpreter to report.
+fn operand<V: Cell>((mode, value): &(ParameterMode, V)) -> Option<Operand<V>> {
+    let value = value.clone();
+    Some(match mode {
+        ParameterMode::Immediate => Box::new(move |_, _| Ok(value.clone())),
+        ParameterMode::Position => {
+            let address = value.to_usize()?;
+            Box::new(move |memory, _| Ok(memory.get(address)))
+        }
+        ParameterMode::Relative => Box::new(move |memory, relative_base| {
+            Ok(memory.get(relative_address(relative_base, &value)?))
+        }),
+    })
+}
+
+/// An operand to write to. Immediate mode is left to the interpreter to report.
+fn target<V: Cell>((mode, value): &(ParameterMode, V)) -> Option<Target<V>> {
+    let value = value.clone();
+    match mode {
+        ParameterMode::Immediate => None,
+        ParameterMode::Position => {
+            let address = value.to_usize()?;
+            Some(Box::new(move |_| Ok(address)))
+        }
+        ParameterMode::Relative => Some(Box::new(move |relative_base| {
+            relative_address(relative_base, &value)
+        })),
+    }
+}
+
+fn binary<V: Cell>(
+    parameters: &[(ParameterMode, V)],
+    operator: fn(&V, &V) -> Result<V, ComputerError<V>>,
+) -> Option<Run<V>> {
+    let a = operand(&parameters[0])?;
+    let b = operand(&parameters[1])?;
+    let to = target(&parameters[2])?;
+    Some(Box::new(move |memory, relative_base| {
+        let value = operator(&a(memory, relative_base)?, &b(memory, relative_base)?)?;
+        let address = to(relative_base)?;
+        *memory.get_mut(address) = value;
+        Ok(Effect::Wrote(address))
+    }))
+}
+
+fn jump<V: Cell>(parameters: &[(ParameterMode, V)], if_true: bool) -> Option<Run<V>> {
+    let condition = operand(&parameters[0])?;
+    let to = operand(&parameters[1])?;
+    Some(Box::new(move |memory, relative_base| {
+        let condition = condition(memory, relative_base)?;
+        let to = to(memory, relative_base)?;
+        if (condition != V::default()) == if_true {
+            Ok(Effect::Jump(
+                to.to_usize().ok_or(ComputerError::InvalidJump)?,
+            ))
+        } else {
+            Ok(Effect::Next)
+        }
+    }))
+}
+
+fn relative_base_offset<V: Cell>(parameters: &[(ParameterMode, V)]) -> Option<Run<V>> {
+    let offset = operand(&parameters[0])?;
+    Some(Box::new(move |memory, relative_base| {
+        *relative_base = relative_base
+            .checked_add(&offset(memory, relative_base)?)
+            .ok_or(ComputerError::ArithmaticError)?;
+        Ok(Effect::Next)
+    }))
+}
+
+impl<'a, V: Cell> Computer<'a, V> {
+    /// Execute compiled blocks until the next instruction has to be interpreted. Blocks are only
+    /// used with `Engine::Compiled`, without a tracer, limits or a code map.
+    pub(crate) fn run_compiled(&mut self) -> Result<(), ComputerError<V>> {
+        if self.engine != Engine::Compiled
+            || self.tracer.is_some()
+            || self.limits != Limits::default()
+            || self.code_map.is_some()
+            || self.history.is_some()
+        {
+            return Ok(());
+        }
+        self.run_blocks().map_err(|error| {
+            let address = self.instruction_pointer;
+            self.fault(address, error)
+        })
+    }
+
+    fn run_blocks(&mut self) -> Result<(), ComputerError<V>> {
+        'blocks: while let Some(block) = self.compiler.block(&self.memory, self.instruction_pointer)
+        {
+            for operation in &block.operations {
+                self.instruction_pointer = operation.address;
+                let effect = (operation.run)(&mut self.memory, &mut self.relative_base)?;
+                self.executed_instructions += 1;
+                match effect {
+                    Effect::Next => {}
+                    Effect::Jump(to) => {
+                        self.instruction_pointer = to;
+                        continue 'blocks;
+                    }
+                    Effect::Wrote(address) => {
+                        self.decoded.invalidate(address);
+                        if self.compiler.invalidate(address) {
+                            self.instruction_pointer = operation.next;
+                            continue 'blocks;
+                        }
+                    }
+                }
+            }
+            self.instruction_pointer = block.end;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_compiled_matches_interpreter() {
+    use crate::intcode::samples::{patches_next, programs, run_with, self_modifying};
+    let compiled = |memory| run_with(Engine::Compiled, memory, |_| 0, false).result;
+    assert_eq!(compiled(self_modifying()), Ok(vec![1, 2]));
+    assert_eq!(compiled(patches_next()), Ok(vec![2]));
+    for (memory, input) in programs() {
+        for &traced in &[false, true] {
+            assert_eq!(
+                run_with(Engine::Compiled, memory.clone(), input, traced),
+                run_with(Engine::Interpreter, memory.clone(), input, traced)
+            );
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use crate::intcode::{
+    analyze, disassemble, Item, Memory, OpCode, ParameterMode, TraceEntry, Tracer,
+};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+/// How often a conditional jump went each way.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct BranchCoverage {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+impl BranchCoverage {
+    pub fn is_covered(&self) -> bool {
+        self.taken > 0 && self.not_taken > 0
+    }
+}
+
+/// Records which instructions were executed and which ways branches went. Install it as the
+/// tracer of a `Computer`, and merge the coverage of several runs with `merge`.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Coverage {
+    executions: HashMap<usize, u64>,
+    op_codes: HashMap<OpCode, u64>,
+    branches: HashMap<usize, BranchCoverage>,
+}
+
+impl Tracer for Coverage {
+    fn trace(&mut self, entry: &TraceEntry) {
+        let address = entry.step.address;
+        let op_code = entry.step.op_code;
+        *self.executions.entry(address).or_insert(0) += 1;
+        *self.op_codes.entry(op_code).or_insert(0) += 1;
+        if op_code != OpCode::JumpIfTrue && op_code != OpCode::JumpIfFalse {
+            return;
+        }
+        let condition = match entry.parameters[0].mode {
+            ParameterMode::Immediate => entry.parameters[0].value,
+            _ => entry.step.reads[0].value,
+        };
+        let branch = self.branches.entry(address).or_default();
+        if (condition != 0) == (op_code == OpCode::JumpIfTrue) {
+            branch.taken += 1;
+        } else {
+            branch.not_taken += 1;
+        }
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage::default()
+    }
+
+    /// Add the coverage of another run.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (address, count) in &other.executions {
+            *self.executions.entry(*address).or_insert(0) += count;
+        }
+        for (op_code, count) in &other.op_codes {
+            *self.op_codes.entry(*op_code).or_insert(0) += count;
+        }
+        for (address, other) in &other.branches {
+            let branch = self.branches.entry(*address).or_default();
+            branch.taken += other.taken;
+            branch.not_taken += other.not_taken;
+        }
+    }
+
+    pub fn executions(&self, address: usize) -> u64 {
+        self.executions.get(&address).copied().unwrap_or(0)
+    }
+
+    pub fn op_code_executions(&self, op_code: OpCode) -> u64 {
+        self.op_codes.get(&op_code).copied().unwrap_or(0)
+    }
+
+    /// How the conditional jump at `address` went, if it was executed.
+    pub fn branch(&self, address: usize) -> Option<BranchCoverage> {
+        self.branches.get(&address).copied()
+    }
+
+    /// The disassembly of `memory`, with the number of executions in front of every line. Code
+    /// that was never executed is marked with `#####`, and branches that did not go both ways with
+    /// `!`.
+    pub fn annotate<'a>(&'a self, memory: &'a Memory) -> CoverageListing<'a> {
+        CoverageListing {
+            coverage: self,
+            memory,
+        }
+    }
+}
+
+pub struct CoverageListing<'a> {
+    coverage: &'a Coverage,
+    memory: &'a Memory,
+}
+
+impl fmt::Display for CoverageListing<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code: BTreeSet<usize> = analyze(self.memory)
+            .blocks()
+            .flat_map(|block| block.instructions.iter().map(Item::address))
+            .chain(self.coverage.executions.keys().copied())
+            .collect();
+        // Counted over all code, as an executed instruction can start inside one that was
+        // disassembled before the program modified itself
+        let covered = code
+            .iter()
+            .filter(|&&address| self.coverage.executions(address) > 0)
+            .count();
+        let (mut branches, mut covered_branches) = (0, 0);
+        for item in disassemble(self.memory).items() {
+            let address = item.address();
+            match self.coverage.executions(address) {
+                0 if code.contains(&address) => write!(f, "{:>12}", "#####")?,
+                0 => write!(f, "{:>12}", "")?,
+                count => write!(f, "{:>12}", count)?,
+            }
+            let branch = match item {
+                // Jumps with an immediate condition always go the same way
+                Item::Instruction {
+                    op_code: OpCode::JumpIfTrue,
+                    parameters,
+                    ..
+                }
+                | Item::Instruction {
+                    op_code: OpCode::JumpIfFalse,
+                    parameters,
+                    ..
+                } if code.contains(&address) && parameters[0].mode != ParameterMode::Immediate => {
+                    Some(self.coverage.branch(address).unwrap_or_default())
+                }
+                _ => None,
+            };
+            match branch {
+                Some(branch) => {
+                    branches += 1;
+                    if branch.is_covered() {
+                        covered_branches += 1;
+                    }
+                    writeln!(
+                        f,
+                        " {}{:>5}: {:<24} taken {}, not taken {}",
+                        if branch.is_covered() { ' ' } else { '!' },
+                        address,
+                        item.to_string(),
+                        branch.taken,
+                        branch.not_taken
+                    )?
+                }
+                None => writeln!(f, "  {:>5}: {}", address, item)?,
+            }
+        }
+        writeln!(
+            f,
+            "{} of {} instructions and {} of {} branches covered",
+            covered,
+            code.len(),
+            covered_branches,
+            branches
+        )
+    }
+}
+
+#[test]
+fn test_coverage() {
+    use crate::intcode::{assemble, Computer, Value};
+    let program = assemble(
+        "
+                in [n]
+                lt [n], #0, [t]
+                jt [t], #negative
+        loop:   jf [n], #done
+                out [n]
+                add [n], #-1, [n]
+                jt #1, #loop
+        done:   hlt
+        negative:
+                out #-1
+                hlt
+        n:      .data 0
+        t:      .data 0
+        ",
+    )
+    .unwrap();
+    let run = |input: Value| {
+        let mut coverage = Coverage::new();
+        let mut computer = Computer::load(program.clone());
+        computer.set_tracer(Some(&mut coverage));
+        computer.provide_input(input);
+        while computer.resume().unwrap() != crate::intcode::Status::Halted {}
+        drop(computer);
+        coverage
+    };
+    let mut coverage = run(2);
+    assert_eq!(coverage.executions(9), 3);
+    assert_eq!(
+        coverage.branch(9),
+        Some(BranchCoverage {
+            taken: 1,
+            not_taken: 2
+        })
+    );
+    assert_eq!(coverage.op_code_executions(OpCode::Output), 2);
+    let listing = coverage.annotate(&program).to_string();
+    assert!(
+        listing.contains("           1 !    6: jt [26], #22             taken 0, not taken 1\n")
+    );
+    assert!(
+        listing.contains("           3      9: jf [25], #21             taken 1, not taken 2\n")
+    );
+    assert!(listing.contains("       #####     24: hlt\n                 25: .data 0\n"));
+    assert!(listing.ends_with("8 of 10 instructions and 1 of 2 branches covered\n"));
+
+    coverage.merge(&run(-1));
+    assert_eq!(coverage.executions(22), 1);
+    let listing = coverage.annotate(&program).to_string();
+    assert!(listing.ends_with("10 of 10 instructions and 2 of 2 branches covered\n"));
+}
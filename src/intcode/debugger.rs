@@ -0,0 +1,361 @@
+use crate::intcode::{Computer, ComputerError, OpCode, Step, Value};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// A condition that is checked before every instruction.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Breakpoint {
+    /// Stop before the instruction at this address.
+    Address(usize),
+    /// Stop before any instruction with this op code.
+    OpCode(OpCode),
+    /// Stop before any instruction while the relative base lies within this range.
+    RelativeBase(RangeInclusive<Value>),
+}
+
+impl Breakpoint {
+    fn is_hit(&self, computer: &Computer<'_>) -> Result<bool, ComputerError> {
+        Ok(match self {
+            Breakpoint::Address(address) => computer.instruction_pointer() == *address,
+            Breakpoint::OpCode(op_code) => computer.next_op_code()? == *op_code,
+            Breakpoint::RelativeBase(range) => range.contains(&computer.relative_base()),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct BreakpointId(usize);
+
+/// The kind of memory access a watchpoint triggers on.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Watch {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Watch {
+    fn on_read(self) -> bool {
+        self != Watch::Write
+    }
+
+    fn on_write(self) -> bool {
+        self != Watch::Read
+    }
+}
+
+/// A memory access that triggered a watchpoint.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Access {
+    Read(Value),
+    Write { old: Value, new: Value },
+}
+
+/// The reason the debugger stopped.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Stop {
+    /// A breakpoint was hit. The instruction at the instruction pointer has not been executed yet.
+    Breakpoint(BreakpointId),
+    /// A watched memory cell was accessed by the instruction that was just executed.
+    Watchpoint {
+        address: usize,
+        access: Access,
+        step: Box<Step>,
+    },
+    /// The program halted.
+    Halted,
+    /// Running backwards reached the oldest state in the history of the computer.
+    HistoryStart,
+}
+
+/// A debugging session around a `Computer`.
+///
+/// Watchpoints work on addresses, so they apply to the loaded image as well as any cells the
+/// program uses beyond it.
+pub struct Debugger<'c, 'a> {
+    computer: &'c mut Computer<'a>,
+    breakpoints: Vec<Option<Breakpoint>>,
+    watchpoints: HashMap<usize, Watch>,
+    /// Whether we stopped at a breakpoint last time, which should not trigger again on resume.
+    at_breakpoint: bool,
+}
+
+impl<'c, 'a> Debugger<'c, 'a> {
+    pub fn new(computer: &'c mut Computer<'a>) -> Self {
+        Debugger {
+            computer,
+            breakpoints: Vec::new(),
+            watchpoints: HashMap::new(),
+            at_breakpoint: false,
+        }
+    }
+
+    pub fn computer(&self) -> &Computer<'a> {
+        self.computer
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> BreakpointId {
+        self.breakpoints.push(Some(breakpoint));
+        BreakpointId(self.breakpoints.len() - 1)
+    }
+
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> Option<Breakpoint> {
+        self.breakpoints.get_mut(id.0).and_then(Option::take)
+    }
+
+    pub fn breakpoint(&self, id: BreakpointId) -> Option<&Breakpoint> {
+        self.breakpoints.get(id.0).and_then(Option::as_ref)
+    }
+
+    /// Watch a memory cell, replacing any earlier watchpoint on the same address.
+    pub fn watch(&mut self, address: usize, watch: Watch) {
+        self.watchpoints.insert(address, watch);
+    }
+
+    pub fn unwatch(&mut self, address: usize) -> Option<Watch> {
+        self.watchpoints.remove(&address)
+    }
+
+    /// Execute a single instruction, ignoring breakpoints and watchpoints.
+    pub async fn step(&mut self) -> Result<Step, ComputerError> {
+        self.at_breakpoint = false;
+        self.computer.step().await
+    }
+
+    /// Run until a breakpoint or watchpoint triggers, or until the program halts.
+    pub async fn resume(&mut self) -> Result<Stop, ComputerError> {
+        loop {
+            if !self.at_breakpoint {
+                if let Some(id) = self.hit_breakpoint()? {
+                    self.at_breakpoint = true;
+                    return Ok(Stop::Breakpoint(id));
+                }
+            }
+            self.at_breakpoint = false;
+            let step = self.computer.step().await?;
+            if let Some((address, access)) = self.hit_watchpoint(&step) {
+                return Ok(Stop::Watchpoint {
+                    address,
+                    access,
+                    step: Box::new(step),
+                });
+            }
+            if step.halted() {
+                return Ok(Stop::Halted);
+            }
+        }
+    }
+
+    /// Undo a single instruction, ignoring breakpoints and watchpoints. Returns `None` if there is
+    /// no history left, see `Computer::set_history`.
+    pub fn reverse_step(&mut self) -> Option<Step> {
+        self.at_breakpoint = false;
+        self.computer.reverse_step()
+    }
+
+    /// Run backwards until a breakpoint or watchpoint triggers, or until the oldest state in the
+    /// history. A watchpoint stops before the instruction that accessed the cell.
+    pub fn reverse_resume(&mut self) -> Result<Stop, ComputerError> {
+        self.at_breakpoint = false;
+        loop {
+            let step = match self.computer.reverse_step() {
+                Some(step) => step,
+                None => return Ok(Stop::HistoryStart),
+            };
+            if let Some((address, access)) = self.hit_watchpoint(&step) {
+                return Ok(Stop::Watchpoint {
+                    address,
+                    access,
+                    step: Box::new(step),
+                });
+            }
+            if let Some(id) = self.hit_breakpoint()? {
+                self.at_breakpoint = true;
+                return Ok(Stop::Breakpoint(id));
+            }
+        }
+    }
+
+    fn hit_breakpoint(&self) -> Result<Option<BreakpointId>, ComputerError> {
+        for (id, breakpoint) in self.breakpoints.iter().enumerate() {
+            if let Some(breakpoint) = breakpoint {
+                if breakpoint.is_hit(self.computer)? {
+                    return Ok(Some(BreakpointId(id)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn hit_watchpoint(&self, step: &Step) -> Option<(usize, Access)> {
+        if self.watchpoints.is_empty() {
+            return None;
+        }
+        let watched = |address, on_access: fn(Watch) -> bool| match self.watchpoints.get(&address) {
+            Some(watch) => on_access(*watch),
+            None => false,
+        };
+        step.reads
+            .iter()
+            .filter(|read| watched(read.address, Watch::on_read))
+            .map(|read| (read.address, Access::Read(read.value)))
+            .chain(
+                step.write
+                    .filter(|write| watched(write.address, Watch::on_write))
+                    .map(|write| {
+                        (
+                            write.address,
+                            Access::Write {
+                                old: write.old,
+                                new: write.new,
+                            },
+                        )
+                    }),
+            )
+            .next()
+    }
+}
+
+#[futures_await_test::async_test]
+async fn test_breakpoints() {
+    use crate::intcode::assemble;
+    let program = assemble(
+        "
+                add #3, #0, [counter]
+        loop:   out [counter]
+                add [counter], #-1, [counter]
+                jt [counter], #loop
+                rbo #5
+                hlt
+        counter: .data 0
+        ",
+    )
+    .unwrap();
+    let mut output = Vec::new();
+    let mut computer = Computer::load(program);
+    computer.set_output(Some(&mut output));
+    let mut debugger = Debugger::new(&mut computer);
+
+    let at_loop = debugger.add_breakpoint(Breakpoint::Address(4));
+    for _ in 0..3 {
+        assert_eq!(debugger.resume().await.unwrap(), Stop::Breakpoint(at_loop));
+        assert_eq!(debugger.computer().instruction_pointer(), 4);
+    }
+    debugger.remove_breakpoint(at_loop);
+
+    let on_offset = debugger.add_breakpoint(Breakpoint::OpCode(OpCode::RelativeBaseOffset));
+    let on_base = debugger.add_breakpoint(Breakpoint::RelativeBase(1..=10));
+    assert_eq!(
+        debugger.resume().await.unwrap(),
+        Stop::Breakpoint(on_offset)
+    );
+    assert_eq!(debugger.computer().instruction_pointer(), 13);
+    assert_eq!(debugger.resume().await.unwrap(), Stop::Breakpoint(on_base));
+    assert_eq!(debugger.computer().instruction_pointer(), 15);
+    assert_eq!(debugger.resume().await.unwrap(), Stop::Halted);
+    drop(debugger);
+    assert_eq!(output, vec![3, 2, 1]);
+}
+
+#[futures_await_test::async_test]
+async fn test_watchpoints() {
+    use crate::intcode::parse_program;
+    // Writes to an address beyond the image, then reads it back
+    let program = parse_program("1101,4,5,100,4,100,99").unwrap();
+    let mut computer = Computer::load(program);
+    let mut debugger = Debugger::new(&mut computer);
+    debugger.watch(100, Watch::ReadWrite);
+    match debugger.resume().await.unwrap() {
+        Stop::Watchpoint {
+            address: 100,
+            access: Access::Write { old: 0, new: 9 },
+            step,
+        } => assert_eq!(step.op_code, OpCode::Add),
+        other => panic!("unexpected stop: {:?}", other),
+    }
+    match debugger.resume().await.unwrap() {
+        Stop::Watchpoint {
+            address: 100,
+            access: Access::Read(9),
+            step,
+        } => assert_eq!(step.output, Some(9)),
+        other => panic!("unexpected stop: {:?}", other),
+    }
+    assert_eq!(debugger.resume().await.unwrap(), Stop::Halted);
+}
+
+#[futures_await_test::async_test]
+async fn test_reverse() {
+    use crate::intcode::{assemble, parse_program, HistoryLimits, Status};
+    use std::fs;
+    let program = assemble(
+        "
+                add #3, #0, [counter]
+        loop:   out [counter]
+                add [counter], #-1, [counter]
+                jt [counter], #loop
+                rbo #5
+                hlt
+        counter: .data 0
+        ",
+    )
+    .unwrap();
+    let mut output = Vec::new();
+    let mut computer = Computer::load(program);
+    computer.set_output(Some(&mut output));
+    computer.set_history(Some(HistoryLimits::default()));
+    let mut debugger = Debugger::new(&mut computer);
+    assert_eq!(debugger.resume().await.unwrap(), Stop::Halted);
+
+    debugger.watch(16, Watch::Write);
+    match debugger.reverse_resume().unwrap() {
+        Stop::Watchpoint {
+            address: 16,
+            access: Access::Write { old: 1, new: 0 },
+            ..
+        } => {}
+        other => panic!("unexpected stop: {:?}", other),
+    }
+    assert_eq!(debugger.computer().instruction_pointer(), 6);
+    assert_eq!(debugger.computer().relative_base(), 0);
+    debugger.unwatch(16);
+    let at_loop = debugger.add_breakpoint(Breakpoint::Address(4));
+    assert_eq!(
+        debugger.reverse_resume().unwrap(),
+        Stop::Breakpoint(at_loop)
+    );
+    assert_eq!(debugger.computer().memory().get(16), 1);
+    assert_eq!(debugger.reverse_step().unwrap().op_code, OpCode::JumpIfTrue);
+    debugger.remove_breakpoint(at_loop);
+    assert_eq!(debugger.reverse_resume().unwrap(), Stop::HistoryStart);
+    assert_eq!(debugger.computer().executed_instructions(), 0);
+    assert_eq!(debugger.resume().await.unwrap(), Stop::Halted);
+    drop(debugger);
+    drop(computer);
+    assert_eq!(output, vec![3, 2, 1, 3, 2, 1]);
+
+    // Walk back from the end of a lost game of breakout to the last move of the joystick
+    let mut arcade = parse_program(&fs::read_to_string("input/day13").unwrap()).unwrap();
+    *arcade.get_mut(0) = 2;
+    let mut computer = Computer::load(arcade);
+    computer.set_history(Some(HistoryLimits::default()));
+    loop {
+        match computer.resume().unwrap() {
+            Status::NeedsInput => computer.provide_input(0),
+            Status::Output(_) => {}
+            Status::Halted => break,
+        }
+    }
+    let end = computer.executed_instructions();
+    let mut debugger = Debugger::new(&mut computer);
+    let on_input = debugger.add_breakpoint(Breakpoint::OpCode(OpCode::Input));
+    assert_eq!(
+        debugger.reverse_resume().unwrap(),
+        Stop::Breakpoint(on_input)
+    );
+    let last_move = debugger.computer().executed_instructions();
+    assert!(last_move < end);
+    debugger.remove_breakpoint(on_input);
+    assert_eq!(debugger.resume().await.unwrap(), Stop::Halted);
+    assert_eq!(debugger.computer().executed_instructions(), end);
+}
@@ -0,0 +1,93 @@
+//! Decoded instructions for `Engine::Decoded`.
+//!
+//! Only instructions that lie entirely within the loaded image are cached. A write to the image
+//! drops every cached instruction that covers the written cell, so self-modifying programs see the
+//! same behavior as with the interpreter.
+
+use crate::intcode::{Cell, Memory, OpCode, ParameterMode};
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub(crate) struct DecodedInstruction<V: Cell> {
+    pub op_code: OpCode,
+    modes: [ParameterMode; 3],
+    values: [V; 3],
+}
+
+impl<V: Cell> DecodedInstruction<V> {
+    pub fn parameters(&self) -> impl Iterator<Item = (ParameterMode, V)> + '_ {
+        self.modes
+            .iter()
+            .copied()
+            .zip(self.values.iter().cloned())
+            .take(self.op_code.parameter_count())
+    }
+}
+
+/// The longest instruction, so a write can only affect instructions starting this far back.
+const MAX_INSTRUCTION_LENGTH: usize = 4;
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DecodeCache<V: Cell> {
+    instructions: Vec<Option<DecodedInstruction<V>>>,
+}
+
+impl<V: Cell> DecodeCache<V> {
+    /// The instruction at `address`, or `None` if it cannot be decoded or lies outside the image.
+    pub fn get(&mut self, memory: &Memory<V>, address: usize) -> Option<DecodedInstruction<V>> {
+        let image = memory.base();
+        if address >= image.len() {
+            return None;
+        }
+        if self.instructions.len() != image.len() {
+            self.instructions = vec![None; image.len()];
+        }
+        if self.instructions[address].is_none() {
+            self.instructions[address] = decode(image, address);
+        }
+        self.instructions[address].clone()
+    }
+
+    /// Forget the instructions that include the cell at `address`.
+    pub fn invalidate(&mut self, address: usize) {
+        let start = address.saturating_sub(MAX_INSTRUCTION_LENGTH - 1);
+        let end = (address + 1).min(self.instructions.len());
+        for cached in self.instructions.iter_mut().take(end).skip(start) {
+            *cached = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.instructions.clear();
+    }
+}
+
+fn decode<V: Cell>(image: &[V], address: usize) -> Option<DecodedInstruction<V>> {
+    let (mut modes, op_code) = image[address].div_rem(100);
+    let op_code = OpCode::from_code(op_code)?;
+    let mut instruction = DecodedInstruction {
+        op_code,
+        modes: [ParameterMode::Immediate; 3],
+        values: Default::default(),
+    };
+    for i in 0..op_code.parameter_count() {
+        let (rest, mode) = modes.div_rem(10);
+        instruction.modes[i] = ParameterMode::from_code(mode)?;
+        instruction.values[i] = image.get(address + 1 + i)?.clone();
+        modes = rest;
+    }
+    Some(instruction)
+}
+
+#[test]
+fn test_engines_agree() {
+    use crate::intcode::samples::{programs, run_with};
+    use crate::intcode::Engine;
+    for (memory, input) in programs() {
+        for &traced in &[false, true] {
+            assert_eq!(
+                run_with(Engine::Decoded, memory.clone(), input, traced),
+                run_with(Engine::Interpreter, memory.clone(), input, traced)
+            );
+        }
+    }
+}
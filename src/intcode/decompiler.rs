@@ -0,0 +1,980 @@
+//! Lifting of Intcode into structured pseudo-code.
+//!
+//! Every function found by [`analyze`] is decompiled on its own. Forward branches become `if`
+//! statements, backward jumps become loops, and whatever does not fit those shapes is left as a
+//! `goto`. Relative operands are named after their offset from the relative base at the entry of
+//! the function, so `frame[0]` is the return address and the arguments follow it. A comparison that
+//! only feeds the branch after it is folded into the condition, unless the function reads its
+//! result again before overwriting it.
+//!
+//! Programs index into tables by patching the parameter of a later instruction. Such parameters
+//! are shown as the indirect access they amount to, and the regions of the image they point into
+//! are listed as tables.
+
+use crate::intcode::cfg::{BasicBlock, Exit, Function, Target};
+use crate::intcode::{
+    analyze, ControlFlowGraph, Item, Memory, OpCode, Parameter, ParameterMode, Value,
+};
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+
+/// The number of values of a table that are shown.
+const TABLE_EXCERPT: usize = 16;
+
+/// A region of the image that is indexed by patched parameters.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Table {
+    pub start: usize,
+    pub values: Vec<Value>,
+}
+
+/// The pseudo-code of a program.
+#[derive(Clone, Debug)]
+pub struct Decompiled {
+    tables: Vec<Table>,
+    functions: Vec<(Function, Vec<Statement>)>,
+}
+
+impl Decompiled {
+    pub fn tables(&self) -> &[Table] {
+        &self.tables
+    }
+}
+
+/// Decompile the code reachable from address zero.
+pub fn decompile(memory: &Memory) -> Decompiled {
+    let graph = analyze(memory);
+    let patched = patched_parameters(&graph);
+    let tables = tables(&graph, memory, &patched);
+    let starts = tables.iter().map(|table| table.start).collect();
+    let functions = graph
+        .functions()
+        .iter()
+        .map(|function| {
+            let decompiler = FunctionDecompiler::new(&graph, function, &patched, &starts);
+            let body = decompiler.region(0..decompiler.blocks.len(), None, None);
+            (function.clone(), body)
+        })
+        .collect();
+    Decompiled { tables, functions }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct Condition {
+    left: String,
+    operator: &'static str,
+    right: String,
+}
+
+impl Condition {
+    fn negate(self) -> Condition {
+        let operator = match self.operator {
+            "<" => ">=",
+            ">=" => "<",
+            "==" => "!=",
+            _ => "==",
+        };
+        Condition { operator, ..self }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.left, self.operator, self.right)
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum Statement {
+    Label(usize),
+    Assign(String, String),
+    Output(String),
+    RelativeBase(String),
+    Call(usize),
+    Return,
+    Halt,
+    Break,
+    Continue,
+    Goto(usize),
+    ComputedGoto(String),
+    Invalid(usize),
+    If {
+        condition: Condition,
+        then: Vec<Statement>,
+        otherwise: Vec<Statement>,
+    },
+    Loop(Vec<Statement>),
+    While {
+        condition: Condition,
+        body: Vec<Statement>,
+    },
+}
+
+/// The memory cell a parameter refers to.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Reference {
+    /// An immediate parameter, which refers to no cell.
+    Constant,
+    Absolute(Value),
+    /// Relative to the relative base at the entry of the function.
+    Frame(Value),
+    /// A cell that is only known at run time.
+    Unknown,
+}
+
+impl Reference {
+    fn may_alias(self, other: Reference) -> bool {
+        match (self, other) {
+            (Reference::Constant, _) | (_, Reference::Constant) => false,
+            (Reference::Unknown, _) | (_, Reference::Unknown) => true,
+            _ => self == other,
+        }
+    }
+}
+
+/// The loop a statement is part of, for `break` and `continue`.
+#[derive(Copy, Clone)]
+struct Enclosing {
+    header: usize,
+    exit: Option<usize>,
+}
+
+/// The straight-line part of a basic block.
+struct Lifted {
+    statements: Vec<Statement>,
+    /// When the block ends with a branch, the condition under which it is taken.
+    condition: Option<Condition>,
+    /// When the block ends with a jump to a computed address, that address.
+    target: Option<String>,
+}
+
+struct FunctionDecompiler<'a> {
+    function: &'a Function,
+    blocks: Vec<&'a BasicBlock>,
+    index: HashMap<usize, usize>,
+    /// The relative base at the start of every block, relative to the entry of the function.
+    deltas: HashMap<usize, Option<Value>>,
+    patched: &'a BTreeSet<usize>,
+    tables: &'a BTreeSet<usize>,
+}
+
+impl<'a> FunctionDecompiler<'a> {
+    fn new(
+        graph: &'a ControlFlowGraph,
+        function: &'a Function,
+        patched: &'a BTreeSet<usize>,
+        tables: &'a BTreeSet<usize>,
+    ) -> Self {
+        let blocks: Vec<_> = function
+            .blocks
+            .iter()
+            .filter_map(|start| graph.block(*start))
+            .collect();
+        let index = blocks
+            .iter()
+            .enumerate()
+            .map(|(i, block)| (block.start, i))
+            .collect();
+        let mut deltas = HashMap::new();
+        let mut stack = vec![(function.entry, Some(0))];
+        while let Some((start, delta)) = stack.pop() {
+            let block = match graph.block(start) {
+                Some(block) if !deltas.contains_key(&start) => block,
+                _ => continue,
+            };
+            deltas.insert(start, delta);
+            let delta = block
+                .instructions
+                .iter()
+                .fold(delta, |delta, item| match item {
+                    Item::Instruction {
+                        op_code: OpCode::RelativeBaseOffset,
+                        parameters,
+                        ..
+                    } if parameters[0].mode == ParameterMode::Immediate => {
+                        delta.map(|delta| delta + parameters[0].value)
+                    }
+                    Item::Instruction {
+                        op_code: OpCode::RelativeBaseOffset,
+                        ..
+                    } => None,
+                    _ => delta,
+                });
+            for successor in block.successors() {
+                stack.push((successor, delta));
+            }
+        }
+        FunctionDecompiler {
+            function,
+            blocks,
+            index,
+            deltas,
+            patched,
+            tables,
+        }
+    }
+
+    /// Structure the blocks in `range`, after which control continues at `follow`.
+    fn region(
+        &self,
+        range: Range<usize>,
+        follow: Option<usize>,
+        enclosing: Option<Enclosing>,
+    ) -> Vec<Statement> {
+        let mut statements = Vec::new();
+        let mut i = range.start;
+        while i < range.end {
+            let block = self.blocks[i];
+            let next = if i + 1 < range.end {
+                Some(self.blocks[i + 1].start)
+            } else {
+                follow
+            };
+            let is_header = enclosing.map(|enclosing| enclosing.header) == Some(block.start);
+            if let Some(latch) = self.latch(i, range.end).filter(|_| !is_header) {
+                let exit = if latch + 1 < range.end {
+                    Some(self.blocks[latch + 1].start)
+                } else {
+                    follow
+                };
+                let enclosing = Enclosing {
+                    header: block.start,
+                    exit,
+                };
+                let body = self.region(i..latch + 1, Some(block.start), Some(enclosing));
+                statements.push(loop_statement(body));
+                i = latch + 1;
+                continue;
+            }
+
+            statements.push(Statement::Label(block.start));
+            let lifted = self.lift(block);
+            statements.extend(lifted.statements);
+            match block.exit {
+                Exit::Fallthrough(to) | Exit::Jump(Target::Address(to)) => {
+                    statements.extend(jump(to, next, enclosing))
+                }
+                Exit::Call {
+                    function,
+                    return_to,
+                } => {
+                    statements.push(Statement::Call(function));
+                    statements.extend(jump(return_to, next, enclosing));
+                }
+                Exit::Jump(Target::Computed) => {
+                    statements.push(Statement::ComputedGoto(lifted.target.unwrap_or_default()))
+                }
+                Exit::Branch { taken, not_taken } => {
+                    let condition = lifted.condition.expect("a branch has a condition");
+                    let taken = match taken {
+                        Target::Address(taken) => taken,
+                        Target::Computed => {
+                            statements.push(Statement::If {
+                                condition,
+                                then: vec![Statement::ComputedGoto(
+                                    lifted.target.unwrap_or_default(),
+                                )],
+                                otherwise: Vec::new(),
+                            });
+                            statements.extend(jump(not_taken, next, enclosing));
+                            i += 1;
+                            continue;
+                        }
+                    };
+                    let forward = self
+                        .position(taken, &range, follow)
+                        .filter(|&k| k > i && next == Some(not_taken));
+                    if let Some(k) = forward {
+                        // An else branch ends the then branch with a jump past itself
+                        let otherwise = match self.blocks[k - 1].exit {
+                            Exit::Jump(Target::Address(after)) if k > i + 1 => self
+                                .position(after, &range, follow)
+                                .filter(|&after| after > k)
+                                .map(|end| (after, end)),
+                            _ => None,
+                        };
+                        let (then, otherwise) = match otherwise {
+                            Some((after, end)) => {
+                                let then = self.region(i + 1..k, Some(after), enclosing);
+                                let otherwise = self.region(k..end, Some(after), enclosing);
+                                i = end;
+                                (then, otherwise)
+                            }
+                            None => {
+                                let then = self.region(i + 1..k, Some(taken), enclosing);
+                                i = k;
+                                (then, Vec::new())
+                            }
+                        };
+                        statements.push(Statement::If {
+                            condition: condition.negate(),
+                            then,
+                            otherwise,
+                        });
+                        continue;
+                    } else if next == Some(taken) {
+                        statements.push(Statement::If {
+                            condition: condition.negate(),
+                            then: jump(not_taken, None, enclosing),
+                            otherwise: Vec::new(),
+                        });
+                    } else {
+                        statements.push(Statement::If {
+                            condition,
+                            then: jump(taken, None, enclosing),
+                            otherwise: Vec::new(),
+                        });
+                        statements.extend(jump(not_taken, next, enclosing));
+                    }
+                }
+                Exit::Return => statements.push(Statement::Return),
+                Exit::Halt => statements.push(Statement::Halt),
+                Exit::Invalid => statements.push(Statement::Invalid(block.end)),
+            }
+            i += 1;
+        }
+        statements
+    }
+
+    /// The last block in `i..end` that jumps back to block `i`.
+    fn latch(&self, i: usize, end: usize) -> Option<usize> {
+        let header = self.blocks[i].start;
+        (i..end).rev().find(|&j| match self.blocks[j].exit {
+            Exit::Jump(Target::Address(to))
+            | Exit::Branch {
+                taken: Target::Address(to),
+                ..
+            } => to == header,
+            _ => false,
+        })
+    }
+
+    /// The index of the block at `address` within `range`, or the end of the range if control
+    /// reaches `address` by leaving it.
+    fn position(
+        &self,
+        address: usize,
+        range: &Range<usize>,
+        follow: Option<usize>,
+    ) -> Option<usize> {
+        match self.index.get(&address) {
+            Some(&k) if range.contains(&k) => Some(k),
+            _ if follow == Some(address) => Some(range.end),
+            _ => None,
+        }
+    }
+
+    fn lift(&self, block: &BasicBlock) -> Lifted {
+        let mut lifted = Lifted {
+            statements: Vec::new(),
+            condition: None,
+            target: None,
+        };
+        let mut delta = self.deltas.get(&block.start).copied().flatten();
+        let instructions: Vec<_> = block
+            .instructions
+            .iter()
+            .filter_map(|item| match item {
+                Item::Instruction {
+                    address,
+                    op_code,
+                    parameters,
+                } => Some((*address, *op_code, parameters.as_slice())),
+                Item::Data { .. } => None,
+            })
+            .collect();
+        // The comparison that computes the condition of the final branch
+        let mut comparison = None;
+        if let (
+            Exit::Branch { .. },
+            [.., (compare_at, compare, compared), (branch_at, _, branched)],
+        ) = (block.exit, instructions.as_slice())
+        {
+            if matches!(compare, OpCode::LessThan | OpCode::Equals)
+                && compared[2] == branched[0]
+                && compared[2].mode != ParameterMode::Immediate
+                && !self.is_patched(*branch_at, 0)
+                && !self.is_patched(*compare_at, 2)
+            {
+                comparison = Some(*compare_at);
+            }
+        }
+        for (address, op_code, parameters) in instructions {
+            let operand = |i: usize| self.operand(address, i, parameters[i], delta, false);
+            match op_code {
+                OpCode::Add | OpCode::Multiply | OpCode::LessThan | OpCode::Equals => {
+                    if self.stores_return_address(block, op_code, parameters) {
+                        continue;
+                    }
+                    let table = op_code == OpCode::Add
+                        && parameters[2].mode == ParameterMode::Position
+                        && usize::try_from(parameters[2].value)
+                            .map(|cell| self.patched.contains(&cell))
+                            == Ok(true);
+                    let a = self.operand(address, 0, parameters[0], delta, table);
+                    let b = self.operand(address, 1, parameters[1], delta, table);
+                    let result = self.reference(address, 2, parameters[2], delta);
+                    if Some(address) == comparison && !self.is_read_later(block, result) {
+                        lifted.condition = Some(Condition {
+                            left: a,
+                            operator: binary_operator(op_code),
+                            right: b,
+                        });
+                    } else {
+                        let value = binary(op_code, a, parameters[1], b);
+                        lifted.statements.push(Statement::Assign(operand(2), value));
+                    }
+                }
+                OpCode::Input => lifted
+                    .statements
+                    .push(Statement::Assign(operand(0), "input()".to_string())),
+                OpCode::Output => lifted.statements.push(Statement::Output(operand(0))),
+                OpCode::RelativeBaseOffset => match (parameters[0].mode, delta) {
+                    (ParameterMode::Immediate, Some(known)) => {
+                        delta = Some(known + parameters[0].value)
+                    }
+                    _ => {
+                        lifted.statements.push(Statement::RelativeBase(operand(0)));
+                        delta = None;
+                    }
+                },
+                OpCode::JumpIfTrue | OpCode::JumpIfFalse => {
+                    let jump_if = op_code == OpCode::JumpIfTrue;
+                    let condition = lifted.condition.take().unwrap_or_else(|| Condition {
+                        left: operand(0),
+                        operator: "!=",
+                        right: "0".to_string(),
+                    });
+                    lifted.condition = Some(if jump_if {
+                        condition
+                    } else {
+                        condition.negate()
+                    });
+                    lifted.target = Some(operand(1));
+                }
+                OpCode::Quit => {}
+            }
+        }
+        lifted
+    }
+
+    /// Whether an instruction is the part of a call that stores the return address.
+    fn stores_return_address(
+        &self,
+        block: &BasicBlock,
+        op_code: OpCode,
+        parameters: &[Parameter],
+    ) -> bool {
+        let return_to = match block.exit {
+            Exit::Call { return_to, .. } => return_to as Value,
+            _ => return false,
+        };
+        let identity = if op_code == OpCode::Add { 0 } else { 1 };
+        let values = (parameters[0].value, parameters[1].value);
+        matches!(op_code, OpCode::Add | OpCode::Multiply)
+            && parameters[..2]
+                .iter()
+                .all(|parameter| parameter.mode == ParameterMode::Immediate)
+            && parameters[2].mode == ParameterMode::Relative
+            && (values == (return_to, identity) || values == (identity, return_to))
+    }
+
+    /// Whether `cell` may be read after `block` branches, before it is overwritten. Only the
+    /// blocks of the function are followed, continuing after calls.
+    fn is_read_later(&self, block: &BasicBlock, cell: Reference) -> bool {
+        if cell == Reference::Unknown {
+            return true;
+        }
+        let mut stack = block.successors();
+        let mut visited = BTreeSet::new();
+        while let Some(start) = stack.pop() {
+            if !visited.insert(start) {
+                continue;
+            }
+            let block = match self.index.get(&start) {
+                Some(&i) => self.blocks[i],
+                None => return true,
+            };
+            let mut delta = self.deltas.get(&start).copied().flatten();
+            let mut overwritten = false;
+            for item in &block.instructions {
+                let (address, op_code, parameters) = match item {
+                    Item::Instruction {
+                        address,
+                        op_code,
+                        parameters,
+                    } => (*address, *op_code, parameters),
+                    Item::Data { .. } => return true,
+                };
+                let written = match op_code {
+                    OpCode::Add | OpCode::Multiply | OpCode::LessThan | OpCode::Equals => Some(2),
+                    OpCode::Input => Some(0),
+                    _ => None,
+                };
+                for (i, parameter) in parameters.iter().enumerate() {
+                    let reference = self.reference(address, i, *parameter, delta);
+                    if Some(i) == written {
+                        overwritten = reference == cell;
+                    } else if reference.may_alias(cell) {
+                        return true;
+                    }
+                }
+                if overwritten {
+                    break;
+                }
+                if op_code == OpCode::RelativeBaseOffset {
+                    delta = match (parameters[0].mode, delta) {
+                        (ParameterMode::Immediate, Some(known)) => {
+                            Some(known + parameters[0].value)
+                        }
+                        _ => None,
+                    };
+                }
+            }
+            if overwritten {
+                continue;
+            }
+            match block.exit {
+                Exit::Jump(Target::Computed) | Exit::Invalid => return true,
+                Exit::Branch {
+                    taken: Target::Computed,
+                    ..
+                } => return true,
+                _ => stack.extend(block.successors()),
+            }
+        }
+        false
+    }
+
+    /// The cell parameter `i` of the instruction at `address` refers to.
+    fn reference(
+        &self,
+        address: usize,
+        i: usize,
+        parameter: Parameter,
+        delta: Option<Value>,
+    ) -> Reference {
+        if self.is_patched(address, i) {
+            return Reference::Unknown;
+        }
+        match (parameter.mode, delta) {
+            (ParameterMode::Immediate, _) => Reference::Constant,
+            (ParameterMode::Position, _) => Reference::Absolute(parameter.value),
+            // The relative base starts at zero
+            (ParameterMode::Relative, Some(delta)) if self.function.entry == 0 => {
+                Reference::Absolute(delta + parameter.value)
+            }
+            (ParameterMode::Relative, Some(delta)) => Reference::Frame(delta + parameter.value),
+            (ParameterMode::Relative, None) => Reference::Unknown,
+        }
+    }
+
+    fn is_patched(&self, address: usize, i: usize) -> bool {
+        self.patched.contains(&(address + 1 + i))
+    }
+
+    /// The name of parameter `i` of the instruction at `address`.
+    fn operand(
+        &self,
+        address: usize,
+        i: usize,
+        parameter: Parameter,
+        delta: Option<Value>,
+        table: bool,
+    ) -> String {
+        let in_main = self.function.entry == 0;
+        let relative = |offset: String| match delta {
+            // The relative base starts at zero
+            Some(0) if in_main => format!("mem[{}]", offset),
+            Some(delta) if in_main => format!("mem[{} + {}]", offset, delta),
+            Some(0) => format!("frame[{}]", offset),
+            Some(delta) => format!("frame[{} + {}]", offset, delta),
+            None => format!("rb[{}]", offset),
+        };
+        if self.is_patched(address, i) {
+            let cell = format!("mem[{}]", address + 1 + i);
+            return match parameter.mode {
+                ParameterMode::Immediate => cell,
+                ParameterMode::Position => format!("mem[{}]", cell),
+                ParameterMode::Relative => relative(cell),
+            };
+        }
+        match parameter.mode {
+            ParameterMode::Immediate
+                if table
+                    && usize::try_from(parameter.value)
+                        .map(|start| self.tables.contains(&start))
+                        == Ok(true) =>
+            {
+                format!("&table_{}", parameter.value)
+            }
+            ParameterMode::Immediate => parameter.value.to_string(),
+            ParameterMode::Position => format!("mem[{}]", parameter.value),
+            ParameterMode::Relative => match delta {
+                Some(delta) if in_main => format!("mem[{}]", delta + parameter.value),
+                Some(delta) => format!("frame[{}]", delta + parameter.value),
+                None => format!("rb[{}]", parameter.value),
+            },
+        }
+    }
+}
+
+fn binary_operator(op_code: OpCode) -> &'static str {
+    match op_code {
+        OpCode::Add => "+",
+        OpCode::Multiply => "*",
+        OpCode::LessThan => "<",
+        _ => "==",
+    }
+}
+
+fn binary(op_code: OpCode, a: String, b_parameter: Parameter, b: String) -> String {
+    match (op_code, a.as_str(), b.as_str()) {
+        (OpCode::Add, "0", _) | (OpCode::Multiply, "1", _) => b,
+        (OpCode::Add, _, "0") | (OpCode::Multiply, _, "1") => a,
+        (OpCode::Add, _, _)
+            if b_parameter.mode == ParameterMode::Immediate && b_parameter.value < 0 =>
+        {
+            format!("{} - {}", a, -b_parameter.value)
+        }
+        _ => format!("{} {} {}", a, binary_operator(op_code), b),
+    }
+}
+
+/// How to continue at `to` when control would otherwise reach `next`.
+fn jump(to: usize, next: Option<usize>, enclosing: Option<Enclosing>) -> Vec<Statement> {
+    if next == Some(to) {
+        Vec::new()
+    } else if enclosing.map(|enclosing| enclosing.header) == Some(to) {
+        vec![Statement::Continue]
+    } else if enclosing.and_then(|enclosing| enclosing.exit) == Some(to) {
+        vec![Statement::Break]
+    } else {
+        vec![Statement::Goto(to)]
+    }
+}
+
+/// A loop, as a `while` loop if it starts by checking whether to leave.
+fn loop_statement(mut body: Vec<Statement>) -> Statement {
+    let first = body
+        .iter()
+        .position(|statement| !matches!(statement, Statement::Label(_)));
+    if let Some(first) = first {
+        if let Statement::If {
+            condition,
+            then,
+            otherwise,
+        } = &body[first]
+        {
+            if then.as_slice() == [Statement::Break] && otherwise.is_empty() {
+                let condition = condition.clone().negate();
+                body.remove(first);
+                return Statement::While { condition, body };
+            }
+        }
+    }
+    Statement::Loop(body)
+}
+
+/// The parameters of reachable instructions that are written by reachable instructions.
+fn patched_parameters(graph: &ControlFlowGraph) -> BTreeSet<usize> {
+    let mut parameters = BTreeSet::new();
+    let mut written = BTreeSet::new();
+    for item in graph.blocks().flat_map(|block| &block.instructions) {
+        if let Item::Instruction {
+            address,
+            op_code,
+            parameters: values,
+        } = item
+        {
+            parameters.extend(address + 1..address + item.size());
+            let target = match op_code {
+                OpCode::Input => values.first(),
+                OpCode::Add | OpCode::Multiply | OpCode::LessThan | OpCode::Equals => values.last(),
+                _ => None,
+            };
+            if let Some(target) = target.filter(|target| target.mode == ParameterMode::Position) {
+                if let Ok(target) = usize::try_from(target.value) {
+                    written.insert(target);
+                }
+            }
+        }
+    }
+    parameters.intersection(&written).copied().collect()
+}
+
+/// The regions of the image that are added to a patched parameter, each running up to the next
+/// table, the next instruction or the end of the image.
+fn tables(graph: &ControlFlowGraph, memory: &Memory, patched: &BTreeSet<usize>) -> Vec<Table> {
+    let image = memory.base();
+    let code: BTreeSet<usize> = graph
+        .blocks()
+        .flat_map(|block| block.start..block.end)
+        .collect();
+    let mut starts = BTreeSet::new();
+    for item in graph.blocks().flat_map(|block| &block.instructions) {
+        if let Item::Instruction {
+            op_code: OpCode::Add,
+            parameters,
+            ..
+        } = item
+        {
+            let patches = parameters[2].mode == ParameterMode::Position
+                && usize::try_from(parameters[2].value).map(|to| patched.contains(&to)) == Ok(true);
+            if !patches {
+                continue;
+            }
+            for parameter in &parameters[..2] {
+                if parameter.mode != ParameterMode::Immediate {
+                    continue;
+                }
+                if let Ok(start) = usize::try_from(parameter.value) {
+                    if start < image.len() && !code.contains(&start) {
+                        starts.insert(start);
+                    }
+                }
+            }
+        }
+    }
+    starts
+        .iter()
+        .map(|&start| {
+            let end = [
+                starts.range(start + 1..).next(),
+                code.range(start..).next(),
+                Some(&image.len()),
+            ]
+            .iter()
+            .flatten()
+            .map(|end| **end)
+            .min()
+            .unwrap_or(start);
+            Table {
+                start,
+                values: image[start..end].to_vec(),
+            }
+        })
+        .collect()
+}
+
+fn goto_targets(statements: &[Statement], targets: &mut BTreeSet<usize>) {
+    for statement in statements {
+        match statement {
+            Statement::Goto(to) => {
+                targets.insert(*to);
+            }
+            Statement::If {
+                then, otherwise, ..
+            } => {
+                goto_targets(then, targets);
+                goto_targets(otherwise, targets);
+            }
+            Statement::Loop(body) | Statement::While { body, .. } => goto_targets(body, targets),
+            _ => {}
+        }
+    }
+}
+
+fn write_statements(
+    f: &mut fmt::Formatter<'_>,
+    statements: &[Statement],
+    depth: usize,
+    labels: &BTreeSet<usize>,
+) -> fmt::Result {
+    let indent = "    ".repeat(depth);
+    for statement in statements {
+        match statement {
+            Statement::Label(address) if labels.contains(address) => {
+                writeln!(f, "{}l{}:", "    ".repeat(depth - 1), address)?
+            }
+            Statement::Label(_) => {}
+            Statement::Assign(target, value) => writeln!(f, "{}{} = {};", indent, target, value)?,
+            Statement::Output(value) => writeln!(f, "{}output({});", indent, value)?,
+            Statement::RelativeBase(value) => writeln!(f, "{}rb += {};", indent, value)?,
+            Statement::Call(function) => writeln!(f, "{}f{}();", indent, function)?,
+            Statement::Return => writeln!(f, "{}return;", indent)?,
+            Statement::Halt => writeln!(f, "{}halt;", indent)?,
+            Statement::Break => writeln!(f, "{}break;", indent)?,
+            Statement::Continue => writeln!(f, "{}continue;", indent)?,
+            Statement::Goto(to) => writeln!(f, "{}goto l{};", indent, to)?,
+            Statement::ComputedGoto(to) => writeln!(f, "{}goto *{};", indent, to)?,
+            Statement::Invalid(address) => {
+                writeln!(f, "{}invalid instruction at {};", indent, address)?
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                writeln!(f, "{}if ({}) {{", indent, condition)?;
+                write_statements(f, then, depth + 1, labels)?;
+                if !otherwise.is_empty() {
+                    writeln!(f, "{}}} else {{", indent)?;
+                    write_statements(f, otherwise, depth + 1, labels)?;
+                }
+                writeln!(f, "{}}}", indent)?;
+            }
+            Statement::Loop(body) => {
+                writeln!(f, "{}loop {{", indent)?;
+                write_statements(f, body, depth + 1, labels)?;
+                writeln!(f, "{}}}", indent)?;
+            }
+            Statement::While { condition, body } => {
+                writeln!(f, "{}while ({}) {{", indent, condition)?;
+                write_statements(f, body, depth + 1, labels)?;
+                writeln!(f, "{}}}", indent)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Decompiled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for table in &self.tables {
+            write!(f, "table_{} = [", table.start)?;
+            for (i, value) in table.values.iter().take(TABLE_EXCERPT).enumerate() {
+                write!(f, "{}{}", if i == 0 { "" } else { ", " }, value)?;
+            }
+            if table.values.len() > TABLE_EXCERPT {
+                write!(f, ", ...")?;
+            }
+            writeln!(f, "]; // {} values", table.values.len())?;
+        }
+        for (function, body) in &self.functions {
+            if !self.tables.is_empty() || function.entry != 0 {
+                writeln!(f)?;
+            }
+            match function.frame_size {
+                Some(size) if function.entry != 0 => {
+                    writeln!(f, "function f{}() {{ // frame of {}", function.entry, size)?
+                }
+                _ => writeln!(f, "function f{}() {{", function.entry)?,
+            }
+            let mut labels = BTreeSet::new();
+            goto_targets(body, &mut labels);
+            write_statements(f, body, 1, &labels)?;
+            writeln!(f, "}}")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_decompile() {
+    use crate::intcode::assemble;
+    let program = assemble(
+        "
+                rbo #stack
+                in [n]
+        loop:   lt [n], #1, [t]
+                jt [t], #done
+                eq [n], #3, [t]
+                jf [t], #else
+                out #3
+                jt #1, #next
+        else:   add [n], #0, rb+1
+                add #next, #0, rb+0
+                jt #1, #square
+        next:   add [n], #-1, [n]
+                jt #1, #loop
+        done:   hlt
+        square: rbo #2
+                mul rb-1, rb-1, rb-1
+                out rb-1
+                rbo #-2
+                jf #0, rb+0
+        n:      .data 0
+        t:      .data 0
+        stack:  .data 0
+        ",
+    )
+    .unwrap();
+    assert_eq!(
+        decompile(&program).to_string(),
+        "\
+function f0() {
+    mem[55] = input();
+    while (mem[55] >= 1) {
+        if (mem[55] == 3) {
+            output(3);
+        } else {
+            mem[58] = mem[55];
+            f42();
+        }
+        mem[55] = mem[55] - 1;
+    }
+    halt;
+}
+
+function f42() { // frame of 2
+    frame[1] = frame[1] * frame[1];
+    output(frame[1]);
+    return;
+}
+"
+    );
+}
+
+#[test]
+fn test_decompile_reused_comparison() {
+    use crate::intcode::assemble;
+    // The result of the comparison is output after the branch
+    let program = assemble(
+        "
+                in [n]
+                lt [n], #5, [t]
+                jf [t], #skip
+                out #1
+        skip:   out [t]
+                hlt
+        n:      .data 0
+        t:      .data 0
+        ",
+    )
+    .unwrap();
+    assert_eq!(
+        decompile(&program).to_string(),
+        "\
+function f0() {
+    mem[14] = input();
+    mem[15] = mem[14] < 5;
+    if (mem[15] != 0) {
+        output(1);
+    }
+    output(mem[15]);
+    halt;
+}
+"
+    );
+}
+
+#[test]
+fn test_decompile_tables() {
+    use crate::intcode::parse_program;
+    use std::fs;
+    let load = |day| parse_program(&fs::read_to_string(format!("input/{}", day)).unwrap()).unwrap();
+    // The arcade cabinet looks up tiles on the board
+    let arcade = decompile(&load("day13"));
+    assert_eq!(arcade.tables()[0].start, 639);
+    let source = arcade.to_string();
+    assert!(source.contains(
+        "function f578() { // frame of 3
+    mem[594] = frame[2] * 42;
+    mem[594] = frame[1] + mem[594];
+    mem[594] = &table_639 + mem[594];
+    frame[1] = mem[mem[594]];
+    return;
+}
+"
+    ));
+    // The droid checks its moves against the walls of the maze
+    let droid = decompile(&load("day15"));
+    assert_eq!(droid.tables()[0].start, 252);
+    let source = droid.to_string();
+    assert!(source.contains("mem[211] = &table_252 + mem[1032];"));
+    assert!(source.contains("mem[1044] = mem[mem[211]] < 62;"));
+    assert!(!source.contains("invalid"));
+}
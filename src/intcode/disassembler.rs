@@ -0,0 +1,187 @@
+use crate::intcode::{Cell, Memory, OpCode, ParameterMode, Value};
+use std::fmt;
+
+/// A single decoded parameter of an instruction.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Parameter<V: Cell = Value> {
+    pub mode: ParameterMode,
+    pub value: V,
+}
+
+impl<V: Cell> fmt::Display for Parameter<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            ParameterMode::Position => write!(f, "[{}]", self.value),
+            ParameterMode::Immediate => write!(f, "#{}", self.value),
+            ParameterMode::Relative => {
+                // The sign is taken from the text, as not every cell can be negated
+                let offset = self.value.to_string();
+                if offset.starts_with('-') {
+                    write!(f, "rb{}", offset)
+                } else {
+                    write!(f, "rb+{}", offset)
+                }
+            }
+        }
+    }
+}
+
+/// A line of disassembly: either a decoded instruction or a word that could not be decoded.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Item<V: Cell = Value> {
+    Instruction {
+        address: usize,
+        op_code: OpCode,
+        parameters: Vec<Parameter<V>>,
+    },
+    Data {
+        address: usize,
+        value: V,
+    },
+}
+
+impl<V: Cell> Item<V> {
+    pub fn address(&self) -> usize {
+        match self {
+            Item::Instruction { address, .. } | Item::Data { address, .. } => *address,
+        }
+    }
+
+    /// The number of memory cells this item spans.
+    pub fn size(&self) -> usize {
+        match self {
+            Item::Instruction { parameters, .. } => 1 + parameters.len(),
+            Item::Data { .. } => 1,
+        }
+    }
+}
+
+impl<V: Cell> fmt::Display for Item<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Item::Instruction {
+                op_code,
+                parameters,
+                ..
+            } => write_instruction(f, *op_code, parameters),
+            Item::Data { value, .. } => write!(f, ".data {}", value),
+        }
+    }
+}
+
+pub(crate) fn write_instruction<V: Cell>(
+    f: &mut fmt::Formatter<'_>,
+    op_code: OpCode,
+    parameters: &[Parameter<V>],
+) -> fmt::Result {
+    write!(f, "{}", op_code.mnemonic())?;
+    for (i, parameter) in parameters.iter().enumerate() {
+        write!(f, "{}{}", if i == 0 { " " } else { ", " }, parameter)?;
+    }
+    Ok(())
+}
+
+/// Decode the item at `address`, falling back to data when the word is not a valid instruction.
+///
+/// A word only counts as an instruction when it would be encoded to exactly the same value again
+/// and all of its parameters lie within the loaded image.
+pub fn decode<V: Cell>(memory: &Memory<V>, address: usize) -> Item<V> {
+    let image = memory.base();
+    let value = image.get(address).cloned().unwrap_or_default();
+    decode_instruction(image, address, &value).unwrap_or(Item::Data { address, value })
+}
+
+fn decode_instruction<V: Cell>(image: &[V], address: usize, value: &V) -> Option<Item<V>> {
+    if *value < V::default() {
+        return None;
+    }
+    let (mut modes, op_code) = value.div_rem(100);
+    let op_code = OpCode::from_code(op_code)?;
+    let mut parameters = Vec::with_capacity(op_code.parameter_count());
+    for i in 1..=op_code.parameter_count() {
+        let (rest, mode) = modes.div_rem(10);
+        modes = rest;
+        parameters.push(Parameter {
+            mode: ParameterMode::from_code(mode)?,
+            value: image.get(address + i)?.clone(),
+        });
+    }
+    if modes != V::default() {
+        return None;
+    }
+    Some(Item::Instruction {
+        address,
+        op_code,
+        parameters,
+    })
+}
+
+/// The disassembly of a complete memory image.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Disassembly {
+    items: Vec<Item>,
+}
+
+impl Disassembly {
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in &self.items {
+            writeln!(f, "{:>5}: {}", item.address(), item)?;
+        }
+        Ok(())
+    }
+}
+
+/// Disassemble the loaded image of `memory` by sweeping over it linearly.
+pub fn disassemble(memory: &Memory) -> Disassembly {
+    let mut items = Vec::new();
+    let mut address = 0;
+    while address < memory.base().len() {
+        let item = decode(memory, address);
+        address += item.size();
+        items.push(item);
+    }
+    Disassembly { items }
+}
+
+#[test]
+fn test_disassemble() {
+    let memory = crate::intcode::parse_program("1101,4,-3,5,204,-2,99,7").unwrap();
+    assert_eq!(
+        disassemble(&memory).to_string(),
+        "    0: add #4, #-3, [5]\n    4: out rb-2\n    6: hlt\n    7: .data 7\n"
+    );
+}
+
+#[test]
+fn test_disassemble_falls_back_to_data() {
+    // Unknown op code, unused mode digits and a truncated instruction
+    let memory = crate::intcode::parse_program("42,10099,1,0").unwrap();
+    let items = disassemble(&memory);
+    assert_eq!(
+        items.items(),
+        &[
+            Item::Data {
+                address: 0,
+                value: 42
+            },
+            Item::Data {
+                address: 1,
+                value: 10099
+            },
+            Item::Data {
+                address: 2,
+                value: 1
+            },
+            Item::Data {
+                address: 3,
+                value: 0
+            },
+        ]
+    );
+}
@@ -0,0 +1,314 @@
+//! Stepping a `Computer` backwards.
+//!
+//! While history is enabled, every executed instruction is logged with what it changed, so it can
+//! be undone. To bound the memory used, the log only reaches back to the latest checkpoint, a copy
+//! of the state taken every `checkpoint_interval` instructions. Stepping back past a checkpoint
+//! restores the one before it and executes the instructions in between again, using the recorded
+//! input. Only the last `checkpoints` checkpoints are kept, which limits how far back it can go.
+
+use crate::intcode::{Cell, Computer, Memory, OpCode, Step, Value};
+use std::collections::VecDeque;
+
+/// How much history a `Computer` keeps.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct HistoryLimits {
+    /// The number of instructions between checkpoints.
+    pub checkpoint_interval: u64,
+    /// The number of checkpoints to keep.
+    pub checkpoints: usize,
+}
+
+impl Default for HistoryLimits {
+    fn default() -> Self {
+        HistoryLimits {
+            checkpoint_interval: 1024,
+            checkpoints: 64,
+        }
+    }
+}
+
+/// An executed instruction and the relative base before it.
+#[derive(Clone, Debug)]
+struct Undo<V: Cell> {
+    step: Step<V>,
+    relative_base: V,
+}
+
+#[derive(Clone, Debug)]
+struct Checkpoint<V: Cell> {
+    executed_instructions: u64,
+    outputs: u64,
+    memory: Memory<V>,
+    instruction_pointer: usize,
+    relative_base: V,
+}
+
+/// The recorded execution of a `Computer`, see `Computer::set_history`.
+#[derive(Clone, Debug)]
+pub struct History<V: Cell = Value> {
+    limits: HistoryLimits,
+    /// The oldest first. The state at the last one is where `undo` starts.
+    checkpoints: VecDeque<Checkpoint<V>>,
+    undo: Vec<Undo<V>>,
+    /// The values read since the oldest checkpoint, with the step that read them.
+    inputs: VecDeque<(u64, V)>,
+}
+
+impl<V: Cell> History<V> {
+    fn new(limits: HistoryLimits) -> Self {
+        History {
+            limits,
+            checkpoints: VecDeque::new(),
+            undo: Vec::new(),
+            inputs: VecDeque::new(),
+        }
+    }
+
+    pub fn limits(&self) -> HistoryLimits {
+        self.limits
+    }
+
+    /// The number of executed instructions at the oldest state that can be returned to.
+    pub fn oldest(&self) -> Option<u64> {
+        self.checkpoints
+            .front()
+            .map(|checkpoint| checkpoint.executed_instructions)
+    }
+}
+
+impl<'a, V: Cell> Computer<'a, V> {
+    /// Start or stop recording history for `reverse_step`. Recording starts from the current
+    /// state, and stopping it drops the history.
+    pub fn set_history(&mut self, limits: Option<HistoryLimits>) {
+        self.history = limits.map(History::new);
+    }
+
+    pub fn history(&self) -> Option<&History<V>> {
+        self.history.as_ref()
+    }
+
+    /// Record an executed instruction, given the relative base before it.
+    pub(crate) fn record_history(&mut self, step: &Step<V>, relative_base: V) {
+        let history = match &mut self.history {
+            Some(history) => history,
+            None => return,
+        };
+        if let Some(input) = &step.input {
+            history
+                .inputs
+                .push_back((self.executed_instructions - 1, input.clone()));
+        }
+        history.undo.push(Undo {
+            step: step.clone(),
+            relative_base,
+        });
+    }
+
+    /// Drop the record of the last executed instruction, which was taken back.
+    pub(crate) fn forget_history(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.undo.pop();
+        }
+    }
+
+    /// Take a checkpoint of the current state if the last one is too old.
+    pub(crate) fn checkpoint_history(&mut self) {
+        let history = match &mut self.history {
+            Some(history) => history,
+            None => return,
+        };
+        if !history.checkpoints.is_empty()
+            && (history.undo.len() as u64) < history.limits.checkpoint_interval
+        {
+            return;
+        }
+        history.undo.clear();
+        history.checkpoints.push_back(Checkpoint {
+            executed_instructions: self.executed_instructions,
+            outputs: self.outputs,
+            memory: self.memory.clone(),
+            instruction_pointer: self.instruction_pointer,
+            relative_base: self.relative_base.clone(),
+        });
+        if history.checkpoints.len() > history.limits.checkpoints.max(1) {
+            history.checkpoints.pop_front();
+            let oldest = history.checkpoints[0].executed_instructions;
+            while history.inputs.front().map(|(step, _)| *step < oldest) == Some(true) {
+                history.inputs.pop_front();
+            }
+        }
+    }
+
+    /// Undo the last executed instruction, returning it, or `None` if there is no history left.
+    ///
+    /// The instruction pointer moves back to the instruction, and an input it read is queued
+    /// again, so executing forward repeats the same steps. Output that was written stays written,
+    /// and a code map starts over empty.
+    pub fn reverse_step(&mut self) -> Option<Step<V>> {
+        if self.history.as_ref()?.undo.is_empty() && !self.rewind_checkpoint() {
+            return None;
+        }
+        let history = self.history.as_mut()?;
+        let Undo {
+            step,
+            relative_base,
+        } = history.undo.pop()?;
+        if step.input.is_some() {
+            history.inputs.pop_back();
+        }
+        if let Some(write) = &step.write {
+            self.decoded.invalidate(write.address);
+            self.compiler.invalidate(write.address);
+            *self.memory.get_mut(write.address) = write.old.clone();
+        }
+        if let Some(input) = &step.input {
+            self.pending_input.push_front(input.clone());
+        }
+        if step.output.is_some() {
+            self.outputs -= 1;
+        }
+        self.instruction_pointer = step.address;
+        self.relative_base = relative_base;
+        self.executed_instructions -= 1;
+        self.halted = false;
+        self.clear_code_map();
+        Some(step)
+    }
+
+    /// Rebuild the undo log up to the last checkpoint, from the one before it. Returns false if
+    /// there is no earlier checkpoint.
+    fn rewind_checkpoint(&mut self) -> bool {
+        let mut history = match self.history.take() {
+            Some(history) => history,
+            None => return false,
+        };
+        if history.checkpoints.len() < 2 {
+            self.history = Some(history);
+            return false;
+        }
+        // The last checkpoint is the current state, `checkpoint_interval` instructions after the
+        // one before it
+        history.checkpoints.pop_back();
+        let start = history.checkpoints.back().unwrap().clone();
+        let mut inputs = history
+            .inputs
+            .iter()
+            .filter(|(step, _)| *step >= start.executed_instructions)
+            .map(|(_, value)| value.clone())
+            .collect::<Vec<_>>()
+            .into_iter();
+        self.memory = start.memory;
+        self.decoded.clear();
+        self.compiler.clear();
+        self.instruction_pointer = start.instruction_pointer;
+        self.relative_base = start.relative_base;
+        self.executed_instructions = start.executed_instructions;
+        self.outputs = start.outputs;
+        self.halted = false;
+        // The instructions succeeded before, so they will again, even if the limits were lowered
+        // since. They are not tracked, as the code map starts over after stepping back
+        let code_map = self.code_map.take();
+        let limits = std::mem::take(&mut self.limits);
+        for _ in 0..history.limits.checkpoint_interval {
+            let input = match self.next_op_code() {
+                Ok(OpCode::Input) => inputs.next(),
+                _ => None,
+            };
+            let relative_base = self.relative_base.clone();
+            let step = self
+                .execute_instruction(input)
+                .expect("replaying recorded instructions");
+            history.undo.push(Undo {
+                step,
+                relative_base,
+            });
+        }
+        self.code_map = code_map;
+        self.limits = limits;
+        self.history = Some(history);
+        true
+    }
+}
+
+#[test]
+fn test_reverse_step() {
+    use crate::intcode::{assemble, ComputerError, Limits, Status};
+    let program = assemble(
+        "
+        loop:   in [n]
+                jf [n], #end
+                add [sum], [n], [sum]
+                out [sum]
+                jt #1, #loop
+        end:    hlt
+        n:      .data 0
+        sum:    .data 0
+        ",
+    )
+    .unwrap();
+    let mut computer = Computer::load(program.clone());
+    computer.set_history(Some(HistoryLimits {
+        checkpoint_interval: 3,
+        checkpoints: 4,
+    }));
+    for input in &[5, 7] {
+        computer.provide_input(*input);
+    }
+    assert_eq!(computer.resume(), Ok(Status::Output(5)));
+    assert_eq!(computer.resume(), Ok(Status::Output(12)));
+    let after = computer.snapshot();
+    assert_eq!(computer.executed_instructions(), 9);
+
+    // Back to before the second input
+    let mut undone = Vec::new();
+    while computer.executed_instructions() > 5 {
+        undone.push(computer.reverse_step().unwrap().op_code);
+    }
+    assert_eq!(
+        undone,
+        vec![
+            OpCode::Output,
+            OpCode::Add,
+            OpCode::JumpIfFalse,
+            OpCode::Input
+        ]
+    );
+    assert_eq!(computer.instruction_pointer(), 0);
+    assert_eq!(computer.memory().get(15), 5);
+    assert_eq!(computer.resume(), Ok(Status::Output(12)));
+    assert_eq!(computer.snapshot(), after);
+
+    // The inputs that were read are queued again, and only four checkpoints of three
+    // instructions are kept
+    while computer.reverse_step().is_some() {}
+    assert_eq!(computer.executed_instructions(), 0);
+    assert_eq!(computer.history().unwrap().oldest(), Some(0));
+    computer.provide_input(0);
+    assert_eq!(computer.resume(), Ok(Status::Output(5)));
+    assert_eq!(computer.resume(), Ok(Status::Output(12)));
+    assert_eq!(computer.resume(), Ok(Status::Halted));
+    let mut steps = 0;
+    while computer.reverse_step().is_some() {
+        steps += 1;
+    }
+    assert_eq!(steps, 10);
+    assert_eq!(computer.executed_instructions(), 3);
+    assert_eq!(computer.history().unwrap().oldest(), Some(3));
+
+    // Lowering the limits does not stop the instructions from being replayed
+    let mut computer = Computer::load(program);
+    computer.set_history(Some(HistoryLimits {
+        checkpoint_interval: 3,
+        checkpoints: 4,
+    }));
+    computer.provide_input(5);
+    assert_eq!(computer.resume(), Ok(Status::Output(5)));
+    computer.set_limits(Limits {
+        instructions: Some(1),
+        ..Limits::default()
+    });
+    while computer.reverse_step().is_some() {}
+    assert_eq!(computer.executed_instructions(), 0);
+    let error = computer.resume().unwrap_err();
+    assert_eq!(error.kind(), &ComputerError::InstructionLimitExceeded(1));
+}
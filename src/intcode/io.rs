@@ -1,23 +1,58 @@
 use async_trait::async_trait;
-use futures::channel::mpsc::{Receiver, Sender};
+use futures::channel::mpsc::{Receiver, SendError, Sender};
 use futures::prelude::*;
 use futures_await_test::async_test;
+use thiserror::*;
 
-use crate::intcode::Value;
+use crate::intcode::{Cell, Value};
+
+mod adapters;
+mod ascii;
+mod stream;
+pub use adapters::*;
+pub use ascii::*;
+pub use stream::*;
+
+/// An error of a reader or a writer.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum IoError {
+    /// The other end of a channel was dropped.
+    #[error("the channel is closed")]
+    Closed,
+    /// An error of a `std::io` stream.
+    #[error("{message}")]
+    Stream {
+        kind: std::io::ErrorKind,
+        message: String,
+    },
+    #[error("{0}")]
+    Other(String),
+}
+
+impl From<SendError> for IoError {
+    fn from(error: SendError) -> Self {
+        if error.is_disconnected() {
+            IoError::Closed
+        } else {
+            IoError::Other(error.to_string())
+        }
+    }
+}
 
 #[async_trait]
-pub trait Read {
-    async fn read(&mut self) -> Option<Value>;
+pub trait Read<V: Cell = Value> {
+    /// Read the next value, or `None` at the end of the input.
+    async fn read(&mut self) -> Result<Option<V>, IoError>;
 }
 
 #[async_trait]
-impl Read for &'_ [Value] {
-    async fn read(&mut self) -> Option<Value> {
+impl<V: Cell> Read<V> for &'_ [V] {
+    async fn read(&mut self) -> Result<Option<V>, IoError> {
         if let Some((value, remainder)) = self.split_first() {
             *self = remainder;
-            Some(*value)
+            Ok(Some(value.clone()))
         } else {
-            None
+            Ok(None)
         }
     }
 }
@@ -25,41 +60,96 @@ impl Read for &'_ [Value] {
 #[async_test]
 async fn test_slice_input() {
     let mut input: &[Value] = &[0, 1, 2];
-    assert_eq!(input.read().await, Some(0));
-    assert_eq!(input.read().await, Some(1));
-    assert_eq!(input.read().await, Some(2));
-    assert_eq!(input.read().await, None);
+    assert_eq!(input.read().await, Ok(Some(0)));
+    assert_eq!(input.read().await, Ok(Some(1)));
+    assert_eq!(input.read().await, Ok(Some(2)));
+    assert_eq!(input.read().await, Ok(None));
 }
 
+/// The input ends when all senders are dropped.
 #[async_trait]
-impl Read for Receiver<Value> {
-    async fn read(&mut self) -> Option<Value> {
-        self.next().await
+impl<V: Cell> Read<V> for Receiver<V> {
+    async fn read(&mut self) -> Result<Option<V>, IoError> {
+        Ok(self.next().await)
     }
 }
 
 #[async_trait]
-pub trait Write {
-    async fn write(&mut self, output: Value);
+pub trait Write<V: Cell = Value> {
+    async fn write(&mut self, output: V) -> Result<(), IoError>;
 }
 
 #[async_trait]
-impl Write for Vec<Value> {
-    async fn write(&mut self, output: Value) {
-        self.push(output)
+impl<V: Cell> Write<V> for Vec<V> {
+    async fn write(&mut self, output: V) -> Result<(), IoError> {
+        self.push(output);
+        Ok(())
     }
 }
 
 #[async_trait]
-impl Write for Option<Value> {
-    async fn write(&mut self, output: Value) {
+impl<V: Cell> Write<V> for Option<V> {
+    async fn write(&mut self, output: V) -> Result<(), IoError> {
         self.replace(output);
+        Ok(())
     }
 }
 
 #[async_trait]
-impl Write for Sender<Value> {
-    async fn write(&mut self, output: Value) {
-        self.send(output).await.unwrap();
+impl<V: Cell> Write<V> for Sender<V> {
+    async fn write(&mut self, output: V) -> Result<(), IoError> {
+        Ok(self.send(output).await?)
     }
 }
+
+#[async_test]
+async fn test_closed_channels() {
+    use crate::intcode::{parse_program, Computer, ComputerError, OpCode, SelfModification};
+    use futures::channel::mpsc::channel;
+    // Echoes its input
+    let program = parse_program("3,9,4,9,1105,1,0,99,99,0").unwrap();
+    let (mut input_sender, mut input) = channel(1);
+    let (mut output, output_receiver) = channel(1);
+    let mut written = Vec::new();
+    input_sender.send(1).await.unwrap();
+    drop(output_receiver);
+    let mut computer = Computer::load(program.clone());
+    computer.set_input(Some(&mut input));
+    computer.set_output(Some(&mut output));
+    computer.set_history(Some(Default::default()));
+    computer.step().await.unwrap();
+    let before = computer.snapshot();
+    let error = computer.run().await.unwrap_err();
+    assert_eq!(error.kind(), &ComputerError::OutputFailed(IoError::Closed));
+    assert_eq!(computer.instruction_pointer(), 2);
+    // The output is taken back, so the next step writes it again
+    assert_eq!(computer.executed_instructions(), 1);
+    assert_eq!(computer.snapshot(), before);
+    computer.set_output(Some(&mut written));
+    assert_eq!(computer.step().await.unwrap().output, Some(1));
+    assert_eq!(computer.executed_instructions(), 2);
+    assert_eq!(computer.reverse_step().unwrap().op_code, OpCode::Output);
+    assert_eq!(computer.reverse_step().unwrap().op_code, OpCode::Input);
+    assert_eq!(computer.reverse_step(), None);
+    drop(computer);
+    assert_eq!(written, vec![1]);
+
+    // An input that could not be stored is read again
+    let mut input: &[Value] = &[5];
+    let mut computer = Computer::load(parse_program("3,0,99").unwrap());
+    computer.set_input(Some(&mut input));
+    computer.set_self_modification(SelfModification::Deny);
+    let error = computer.run().await.unwrap_err();
+    assert_eq!(error.kind(), &ComputerError::CodeModified(0));
+    assert_eq!(computer.executed_instructions(), 0);
+    computer.set_self_modification(SelfModification::Allow);
+    computer.run().await.unwrap();
+    assert_eq!(computer.memory().get(0), 5);
+
+    let (input_sender, mut input) = channel(1);
+    drop(input_sender);
+    let mut computer = Computer::load(program);
+    computer.set_input(Some(&mut input));
+    let error = computer.run().await.unwrap_err();
+    assert_eq!(error.kind(), &ComputerError::ReadInputError);
+}
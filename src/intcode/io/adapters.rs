@@ -0,0 +1,504 @@
+//! Ready-made readers and writers, so a `Computer` can be connected without implementing `Read`
+//! and `Write` for every puzzle.
+
+use crate::intcode::io::{IoError, Read, Write};
+use crate::intcode::Value;
+use async_trait::async_trait;
+use futures::prelude::*;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+#[async_trait]
+impl Read for VecDeque<Value> {
+    async fn read(&mut self) -> Result<Option<Value>, IoError> {
+        Ok(self.pop_front())
+    }
+}
+
+#[async_trait]
+impl Write for VecDeque<Value> {
+    async fn write(&mut self, output: Value) -> Result<(), IoError> {
+        self.push_back(output);
+        Ok(())
+    }
+}
+
+/// A queue of values that clones share, to connect the output of one computer to the input of
+/// another without a channel. Reading an empty queue ends the input.
+#[derive(Clone, Debug, Default)]
+pub struct Queue {
+    values: Arc<Mutex<VecDeque<Value>>>,
+}
+
+impl Queue {
+    pub fn new() -> Self {
+        Queue::default()
+    }
+
+    pub fn push(&self, value: Value) {
+        self.values.lock().unwrap().push_back(value);
+    }
+
+    pub fn pop(&self) -> Option<Value> {
+        self.values.lock().unwrap().pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl Read for Queue {
+    async fn read(&mut self) -> Result<Option<Value>, IoError> {
+        Ok(self.pop())
+    }
+}
+
+#[async_trait]
+impl Write for Queue {
+    async fn write(&mut self, output: Value) -> Result<(), IoError> {
+        self.push(output);
+        Ok(())
+    }
+}
+
+/// Input taken from an iterator.
+#[derive(Clone, Debug)]
+pub struct IteratorInput<I> {
+    values: I,
+}
+
+impl<I: Iterator<Item = Value>> IteratorInput<I> {
+    pub fn new(values: impl IntoIterator<Item = Value, IntoIter = I>) -> Self {
+        IteratorInput {
+            values: values.into_iter(),
+        }
+    }
+}
+
+#[async_trait]
+impl<I: Iterator<Item = Value> + Send> Read for IteratorInput<I> {
+    async fn read(&mut self) -> Result<Option<Value>, IoError> {
+        Ok(self.values.next())
+    }
+}
+
+/// Input produced by a closure.
+pub struct ReadFn<F> {
+    read: F,
+}
+
+impl<F: FnMut() -> Result<Option<Value>, IoError> + Send> ReadFn<F> {
+    pub fn new(read: F) -> Self {
+        ReadFn { read }
+    }
+}
+
+#[async_trait]
+impl<F: FnMut() -> Result<Option<Value>, IoError> + Send> Read for ReadFn<F> {
+    async fn read(&mut self) -> Result<Option<Value>, IoError> {
+        (self.read)()
+    }
+}
+
+/// Output passed to a closure.
+pub struct WriteFn<F> {
+    write: F,
+}
+
+impl<F: FnMut(Value) -> Result<(), IoError> + Send> WriteFn<F> {
+    pub fn new(write: F) -> Self {
+        WriteFn { write }
+    }
+}
+
+#[async_trait]
+impl<F: FnMut(Value) -> Result<(), IoError> + Send> Write for WriteFn<F> {
+    async fn write(&mut self, output: Value) -> Result<(), IoError> {
+        (self.write)(output)
+    }
+}
+
+/// Writes the output to two writers, stopping at the first error. A failed output is written
+/// again by the next step, which skips the first writer if it already took the value.
+#[derive(Clone, Debug, Default)]
+pub struct Tee<A, B> {
+    pub first: A,
+    pub second: B,
+    first_written: bool,
+}
+
+impl<A: Write + Send, B: Write + Send> Tee<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Tee {
+            first,
+            second,
+            first_written: false,
+        }
+    }
+}
+
+#[async_trait]
+impl<A: Write + Send, B: Write + Send> Write for Tee<A, B> {
+    async fn write(&mut self, output: Value) -> Result<(), IoError> {
+        if !self.first_written {
+            self.first.write(output).await?;
+            self.first_written = true;
+        }
+        self.second.write(output).await?;
+        self.first_written = false;
+        Ok(())
+    }
+}
+
+/// Writes the output to any number of writers of the same type, like the senders to several
+/// computers, stopping at the first error. A failed output is written again by the next step,
+/// which skips the writers that already took the value.
+#[derive(Clone, Debug, Default)]
+pub struct Broadcast<W> {
+    pub writers: Vec<W>,
+    written: usize,
+}
+
+impl<W: Write + Send> Broadcast<W> {
+    pub fn new(writers: Vec<W>) -> Self {
+        Broadcast {
+            writers,
+            written: 0,
+        }
+    }
+}
+
+#[async_trait]
+impl<W: Write + Send> Write for Broadcast<W> {
+    async fn write(&mut self, output: Value) -> Result<(), IoError> {
+        while let Some(writer) = self.writers.get_mut(self.written) {
+            writer.write(output).await?;
+            self.written += 1;
+        }
+        self.written = 0;
+        Ok(())
+    }
+}
+
+/// Input from a stream of values of a domain type, like the receiver of a channel of commands.
+#[derive(Debug)]
+pub struct TypedInput<S> {
+    stream: S,
+}
+
+impl<S: Stream + Unpin + Send> TypedInput<S>
+where
+    Value: From<S::Item>,
+{
+    pub fn new(stream: S) -> Self {
+        TypedInput { stream }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+#[async_trait]
+impl<S: Stream + Unpin + Send> Read for TypedInput<S>
+where
+    Value: From<S::Item>,
+{
+    async fn read(&mut self) -> Result<Option<Value>, IoError> {
+        Ok(self.stream.next().await.map(Value::from))
+    }
+}
+
+/// Output converted to a domain type with `TryFrom` and sent into a sink, like the sender of a
+/// channel of status codes. Values that cannot be converted fail the write.
+#[derive(Debug)]
+pub struct TypedOutput<S, T> {
+    sink: S,
+    // Sent, never stored
+    item: PhantomData<fn(T)>,
+}
+
+impl<S, T> TypedOutput<S, T>
+where
+    S: Sink<T> + Unpin + Send,
+    S::Error: Into<IoError>,
+    T: TryFrom<Value> + Send,
+    T::Error: fmt::Display,
+{
+    pub fn new(sink: S) -> Self {
+        TypedOutput {
+            sink,
+            item: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+#[async_trait]
+impl<S, T> Write for TypedOutput<S, T>
+where
+    S: Sink<T> + Unpin + Send,
+    S::Error: Into<IoError>,
+    T: TryFrom<Value> + Send,
+    T::Error: fmt::Display,
+{
+    async fn write(&mut self, output: Value) -> Result<(), IoError> {
+        let item = T::try_from(output).map_err(|error| IoError::Other(error.to_string()))?;
+        self.sink.send(item).await.map_err(Into::into)
+    }
+}
+
+/// A tuple of values that `Chunks` groups output into.
+pub trait Tuple: Sized {
+    const LEN: usize;
+
+    /// Build the tuple from exactly `LEN` values.
+    fn from_values(values: &[Value]) -> Self;
+}
+
+impl Tuple for (Value, Value) {
+    const LEN: usize = 2;
+
+    fn from_values(values: &[Value]) -> Self {
+        (values[0], values[1])
+    }
+}
+
+impl Tuple for (Value, Value, Value) {
+    const LEN: usize = 3;
+
+    fn from_values(values: &[Value]) -> Self {
+        (values[0], values[1], values[2])
+    }
+}
+
+/// Groups the output into tuples, like the `(x, y, tile)` triples of the arcade cabinet, and
+/// passes each complete tuple to a closure. If the closure fails, the last value is dropped from
+/// the tuple, so writing it again completes the same tuple.
+pub struct Chunks<T, F> {
+    buffer: Vec<Value>,
+    write: F,
+    tuple: PhantomData<fn(T)>,
+}
+
+impl<T: Tuple, F: FnMut(T) -> Result<(), IoError> + Send> Chunks<T, F> {
+    pub fn new(write: F) -> Self {
+        Chunks {
+            buffer: Vec::with_capacity(T::LEN),
+            write,
+            tuple: PhantomData,
+        }
+    }
+
+    /// The values of the tuple that is not complete yet.
+    pub fn pending(&self) -> &[Value] {
+        &self.buffer
+    }
+}
+
+#[async_trait]
+impl<T: Tuple, F: FnMut(T) -> Result<(), IoError> + Send> Write for Chunks<T, F> {
+    async fn write(&mut self, output: Value) -> Result<(), IoError> {
+        self.buffer.push(output);
+        if self.buffer.len() < T::LEN {
+            return Ok(());
+        }
+        let result = (self.write)(T::from_values(&self.buffer));
+        match result {
+            Ok(()) => self.buffer.clear(),
+            Err(_) => {
+                self.buffer.pop();
+            }
+        }
+        result
+    }
+}
+
+#[futures_await_test::async_test]
+async fn test_queues() {
+    use crate::intcode::{assemble, Computer};
+    // Adds one to every value
+    let program = assemble(
+        "
+        loop:   in [n]
+                add [n], #1, [n]
+                out [n]
+                jt #1, #loop
+        n:      .data 0
+        ",
+    )
+    .unwrap();
+    // Two computers in a row
+    let queue = Queue::new();
+    let mut input = IteratorInput::new(vec![1, 2, 3]);
+    let mut between = queue.clone();
+    let mut computer = Computer::load(program.clone());
+    computer.set_input(Some(&mut input));
+    computer.set_output(Some(&mut between));
+    assert!(computer.run().await.is_err());
+    assert_eq!(queue.len(), 3);
+
+    let mut between = queue.clone();
+    let mut output = Tee::new(VecDeque::new(), Vec::new());
+    let mut computer = Computer::load(program);
+    computer.set_input(Some(&mut between));
+    computer.set_output(Some(&mut output));
+    assert!(computer.run().await.is_err());
+    drop(computer);
+    assert!(queue.is_empty());
+    assert_eq!(output.first, vec![3, 4, 5]);
+    assert_eq!(output.second, vec![3, 4, 5]);
+}
+
+#[futures_await_test::async_test]
+async fn test_closures_and_chunks() {
+    use crate::intcode::{parse_program, Computer, ComputerError};
+    let program = parse_program("104,1,104,2,104,3,104,4,104,5,3,0,4,0,99").unwrap();
+    let mut tuples = Vec::new();
+    let mut output = Chunks::new(|(a, b): (Value, Value)| {
+        tuples.push((a, b));
+        Ok(())
+    });
+    let mut input = ReadFn::new(|| Ok(Some(6)));
+    let mut computer = Computer::load(program.clone());
+    computer.set_input(Some(&mut input));
+    computer.set_output(Some(&mut output));
+    computer.run().await.unwrap();
+    drop(computer);
+    assert_eq!(output.pending(), &[]);
+    drop(output);
+    assert_eq!(tuples, vec![(1, 2), (3, 4), (5, 6)]);
+
+    let mut output = WriteFn::new(|value| match value {
+        1..=4 => Ok(()),
+        _ => Err(IoError::Other("out of range".to_string())),
+    });
+    let mut computer = Computer::load(program);
+    computer.set_output(Some(&mut output));
+    let error = computer.run().await.unwrap_err();
+    assert_eq!(
+        error.kind(),
+        &ComputerError::OutputFailed(IoError::Other("out of range".to_string()))
+    );
+}
+
+#[futures_await_test::async_test]
+async fn test_retry_after_failed_write() {
+    use crate::intcode::{parse_program, Computer, Memory};
+    /// Takes every value, but fails the first time it sees `fail_on`.
+    struct Flaky {
+        fail_on: Value,
+        values: Vec<Value>,
+    }
+
+    #[async_trait]
+    impl Write for Flaky {
+        async fn write(&mut self, output: Value) -> Result<(), IoError> {
+            if output == self.fail_on {
+                self.fail_on = 0;
+                return Err(IoError::Closed);
+            }
+            self.values.push(output);
+            Ok(())
+        }
+    }
+
+    /// Run until the program halts, stepping again after every failed write.
+    async fn run_retrying(program: Memory, output: &mut (dyn Write + Send)) -> usize {
+        let mut computer = Computer::load(program);
+        computer.set_output(Some(output));
+        let mut failures = 0;
+        while computer.run().await.is_err() {
+            failures += 1;
+        }
+        failures
+    }
+
+    let program = parse_program("104,1,104,2,104,3,104,4,99").unwrap();
+    let flaky = |fail_on| Flaky {
+        fail_on,
+        values: Vec::new(),
+    };
+
+    let mut tuples = Vec::new();
+    let mut failed = false;
+    let mut output = Chunks::new(|tuple: (Value, Value)| {
+        if tuple == (3, 4) && !failed {
+            failed = true;
+            return Err(IoError::Closed);
+        }
+        tuples.push(tuple);
+        Ok(())
+    });
+    assert_eq!(run_retrying(program.clone(), &mut output).await, 1);
+    drop(output);
+    assert_eq!(tuples, vec![(1, 2), (3, 4)]);
+
+    let mut output = Tee::new(flaky(0), flaky(3));
+    assert_eq!(run_retrying(program.clone(), &mut output).await, 1);
+    assert_eq!(output.first.values, vec![1, 2, 3, 4]);
+    assert_eq!(output.second.values, vec![1, 2, 3, 4]);
+
+    let mut output = Broadcast::new(vec![flaky(0), flaky(2), flaky(4)]);
+    assert_eq!(run_retrying(program, &mut output).await, 2);
+    for writer in &output.writers {
+        assert_eq!(writer.values, vec![1, 2, 3, 4]);
+    }
+}
+
+#[futures_await_test::async_test]
+async fn test_typed() {
+    use crate::intcode::{parse_program, Computer, ComputerError};
+    use futures::channel::mpsc::channel;
+
+    #[derive(Debug, Eq, PartialEq)]
+    struct Even(Value);
+
+    impl TryFrom<Value> for Even {
+        type Error = String;
+        fn try_from(value: Value) -> Result<Self, Self::Error> {
+            if value % 2 == 0 {
+                Ok(Even(value))
+            } else {
+                Err(format!("{} is odd", value))
+            }
+        }
+    }
+
+    impl From<Even> for Value {
+        fn from(even: Even) -> Value {
+            even.0
+        }
+    }
+
+    // Doubles its input, then outputs it plus one
+    let program = parse_program("3,0,1002,0,2,0,4,0,1001,0,1,0,4,0,99").unwrap();
+    let (mut sender, receiver) = channel(2);
+    let (output_sender, output_receiver) = channel(2);
+    sender.send(Even(4)).await.unwrap();
+    let mut input = TypedInput::new(receiver);
+    let mut output = TypedOutput::new(output_sender);
+    let mut computer = Computer::load(program);
+    computer.set_input(Some(&mut input));
+    computer.set_output(Some(&mut output));
+    let error = computer.run().await.unwrap_err();
+    assert_eq!(
+        error.kind(),
+        &ComputerError::OutputFailed(IoError::Other("9 is odd".to_string()))
+    );
+    drop(computer);
+    drop(output);
+    assert_eq!(output_receiver.collect::<Vec<Even>>().await, vec![Even(8)]);
+}
@@ -0,0 +1,239 @@
+//! Input and output as ASCII text, for programs that talk to people.
+
+use crate::intcode::io::{IoError, Read, Write};
+use crate::intcode::{Computer, ComputerError, Status, Value};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::fmt;
+
+const NEWLINE: Value = 10;
+
+fn ascii(c: char) -> Result<Value, IoError> {
+    if c.is_ascii() {
+        Ok(Value::from(c as u8))
+    } else {
+        Err(IoError::Other(format!("{:?} is not an ASCII character", c)))
+    }
+}
+
+/// Text fed to a program as character codes, every line followed by a newline.
+#[derive(Clone, Debug, Default)]
+pub struct AsciiInput {
+    characters: VecDeque<char>,
+}
+
+impl AsciiInput {
+    pub fn new(text: &str) -> Self {
+        let mut input = AsciiInput::default();
+        for line in text.lines() {
+            input.push_line(line);
+        }
+        input
+    }
+
+    pub fn push_line(&mut self, line: &str) {
+        self.characters.extend(line.chars());
+        self.characters.push_back('\n');
+    }
+}
+
+#[async_trait]
+impl Read for AsciiInput {
+    async fn read(&mut self) -> Result<Option<Value>, IoError> {
+        self.characters.pop_front().map(ascii).transpose()
+    }
+}
+
+/// Collects output as text. Values outside of the ASCII range, which programs use for their
+/// results, are collected separately.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct AsciiOutput {
+    text: String,
+    values: Vec<Value>,
+}
+
+impl AsciiOutput {
+    pub fn new() -> Self {
+        AsciiOutput::default()
+    }
+
+    pub fn push(&mut self, value: Value) {
+        match value {
+            0..=127 => self.text.push(value as u8 as char),
+            _ => self.values.push(value),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Remove and return the text collected so far.
+    pub fn take_text(&mut self) -> String {
+        std::mem::take(&mut self.text)
+    }
+
+    /// The values outside of the ASCII range, in order.
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+}
+
+#[async_trait]
+impl Write for AsciiOutput {
+    async fn write(&mut self, output: Value) -> Result<(), IoError> {
+        self.push(output);
+        Ok(())
+    }
+}
+
+/// The text a program printed before it wanted input or halted, and the line it was given.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct AsciiExchange {
+    pub output: String,
+    /// `None` if the program halted, or the script ended.
+    pub input: Option<String>,
+}
+
+/// The result of `run_ascii_script`.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct AsciiSession {
+    pub exchanges: Vec<AsciiExchange>,
+    /// The values outside of the ASCII range, in order.
+    pub values: Vec<Value>,
+    pub halted: bool,
+}
+
+/// The session as it would look in a terminal.
+impl fmt::Display for AsciiSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for exchange in &self.exchanges {
+            write!(f, "{}", exchange.output)?;
+            if let Some(input) = &exchange.input {
+                writeln!(f, "{}", input)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run `computer` until it halts, answering every time it wants input with the next line of
+/// `script`. Stops when the program wants more lines than the script has.
+pub fn run_ascii_script<'s>(
+    computer: &mut Computer<'_>,
+    script: impl IntoIterator<Item = &'s str>,
+) -> Result<AsciiSession, ComputerError> {
+    let mut lines = script.into_iter();
+    let mut output = AsciiOutput::new();
+    let mut session = AsciiSession::default();
+    loop {
+        match computer.resume()? {
+            Status::Output(value) => output.push(value),
+            Status::NeedsInput => {
+                let line = lines.next();
+                session.exchanges.push(AsciiExchange {
+                    output: output.take_text(),
+                    input: line.map(str::to_string),
+                });
+                let line = match line {
+                    Some(line) => line,
+                    None => break,
+                };
+                // Converted first, so a line that cannot be sent leaves nothing queued
+                let values = line
+                    .chars()
+                    .map(ascii)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(ComputerError::InputFailed)?;
+                for value in values {
+                    computer.provide_input(value);
+                }
+                computer.provide_input(NEWLINE);
+            }
+            Status::Halted => {
+                session.exchanges.push(AsciiExchange {
+                    output: output.take_text(),
+                    input: None,
+                });
+                session.halted = true;
+                break;
+            }
+        }
+    }
+    session.values = output.values;
+    Ok(session)
+}
+
+#[futures_await_test::async_test]
+async fn test_ascii() {
+    fn echo_program() -> crate::intcode::Memory {
+        // Prompts for a line, echoes it, and outputs its length as a number
+        crate::intcode::assemble(
+            "
+            start:  out #62
+                    out #32
+                    add #0, #0, [length]
+            loop:   in [c]
+                    eq [c], #10, [t]
+                    jt [t], #done
+                    out [c]
+                    add [length], #1, [length]
+                    jt #1, #loop
+            done:   out #10
+                    eq [length], #0, [t]
+                    jt [t], #end
+                    mul [length], #1000, [length]
+                    out [length]
+                    jt #1, #start
+            end:    hlt
+            c:      .data 0
+            t:      .data 0
+            length: .data 0
+            ",
+        )
+        .unwrap()
+    }
+
+    let mut input = AsciiInput::new("ab\nc");
+    let mut output = AsciiOutput::new();
+    let mut computer = Computer::load(echo_program());
+    computer.set_input(Some(&mut input));
+    computer.set_output(Some(&mut output));
+    let error = computer.run().await.unwrap_err();
+    assert_eq!(error.kind(), &ComputerError::ReadInputError);
+    drop(computer);
+    assert_eq!(output.text(), "> ab\n> c\n> ");
+    assert_eq!(output.values(), &[2000, 1000]);
+    let mut input = AsciiInput::new("é");
+    assert!(input.read().await.is_err());
+
+    let mut computer = Computer::load(echo_program());
+    let session = run_ascii_script(&mut computer, vec!["hello", "", "unused"]).unwrap();
+    assert_eq!(session.to_string(), "> hello\nhello\n> \n\n");
+    assert_eq!(
+        session.exchanges[1],
+        AsciiExchange {
+            output: "hello\n> ".to_string(),
+            input: Some(String::new())
+        }
+    );
+    assert_eq!(session.values, vec![5000]);
+    assert!(session.halted);
+
+    // The script ends before the program does
+    let mut computer = Computer::load(echo_program());
+    let session = run_ascii_script(&mut computer, vec!["hi"]).unwrap();
+    assert_eq!(session.exchanges.len(), 2);
+    assert_eq!(session.exchanges[1].input, None);
+    assert!(!session.halted);
+
+    // A line with a character outside of ASCII is not sent at all
+    let mut computer = Computer::load(echo_program());
+    let error = run_ascii_script(&mut computer, vec!["aé"]).unwrap_err();
+    assert_eq!(
+        error,
+        ComputerError::InputFailed(IoError::Other("'é' is not an ASCII character".to_string()))
+    );
+    let session = run_ascii_script(&mut computer, vec!["ok"]).unwrap();
+    assert_eq!(session.values, vec![2000]);
+}
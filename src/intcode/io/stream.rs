@@ -0,0 +1,198 @@
+//! Input and output over `std::io` streams, like a terminal or files. Reading and writing block
+//! the thread.
+
+use crate::intcode::io::{IoError, Read, Write};
+use crate::intcode::Value;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Stdin, Stdout};
+
+/// How values are written as text.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum StreamMode {
+    /// One decimal value per line.
+    Numeric,
+    /// Every value is a character. Output outside of the ASCII range is written as a decimal
+    /// value on a line of its own.
+    Ascii,
+}
+
+/// A broken pipe means whoever was on the other end went away, like a closed channel.
+impl From<io::Error> for IoError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::BrokenPipe => IoError::Closed,
+            kind => IoError::Stream {
+                kind,
+                message: error.to_string(),
+            },
+        }
+    }
+}
+
+/// Input read from a buffered reader, a line at a time. Empty lines are skipped in numeric mode.
+pub struct StreamInput<R> {
+    reader: R,
+    mode: StreamMode,
+    /// The rest of the last line, in ASCII mode.
+    pending: VecDeque<Value>,
+}
+
+impl<R: BufRead + Send> StreamInput<R> {
+    pub fn new(reader: R, mode: StreamMode) -> Self {
+        StreamInput {
+            reader,
+            mode,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl StreamInput<BufReader<Stdin>> {
+    pub fn stdin(mode: StreamMode) -> Self {
+        StreamInput::new(BufReader::new(io::stdin()), mode)
+    }
+}
+
+#[async_trait]
+impl<R: BufRead + Send> Read for StreamInput<R> {
+    async fn read(&mut self) -> Result<Option<Value>, IoError> {
+        match self.mode {
+            StreamMode::Numeric => loop {
+                let mut line = String::new();
+                if self.reader.read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+                let text = line.trim();
+                if !text.is_empty() {
+                    return text
+                        .parse()
+                        .map(Some)
+                        .map_err(|_| IoError::Other(format!("invalid value: {:?}", text)));
+                }
+            },
+            StreamMode::Ascii => {
+                if self.pending.is_empty() {
+                    // Bytes, so text that is not ASCII fails when the program reads it
+                    let mut bytes = Vec::new();
+                    self.reader.read_until(b'\n', &mut bytes)?;
+                    self.pending.extend(bytes.into_iter().map(Value::from));
+                }
+                match self.pending.pop_front() {
+                    Some(byte) if byte > 127 => Err(IoError::Other(format!(
+                        "{} is not an ASCII character",
+                        byte
+                    ))),
+                    byte => Ok(byte),
+                }
+            }
+        }
+    }
+}
+
+/// Output written to a stream, which is flushed after every value so prompts show up.
+pub struct StreamOutput<W> {
+    writer: W,
+    mode: StreamMode,
+}
+
+impl<W: io::Write + Send> StreamOutput<W> {
+    pub fn new(writer: W, mode: StreamMode) -> Self {
+        StreamOutput { writer, mode }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl StreamOutput<Stdout> {
+    pub fn stdout(mode: StreamMode) -> Self {
+        StreamOutput::new(io::stdout(), mode)
+    }
+}
+
+#[async_trait]
+impl<W: io::Write + Send> Write for StreamOutput<W> {
+    async fn write(&mut self, output: Value) -> Result<(), IoError> {
+        match (self.mode, output) {
+            (StreamMode::Ascii, 0..=127) => self.writer.write_all(&[output as u8])?,
+            _ => writeln!(self.writer, "{}", output)?,
+        }
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[futures_await_test::async_test]
+async fn test_streams() {
+    use crate::intcode::{assemble, parse_program, Computer, ComputerError};
+    use std::fs;
+    use std::io::Cursor;
+    // The diagnostic program of day 5, with its system ID from a file
+    let program = parse_program(&fs::read_to_string("input/day5").unwrap()).unwrap();
+    let mut input = StreamInput::new(Cursor::new("\n5\n"), StreamMode::Numeric);
+    let mut output = StreamOutput::new(Vec::new(), StreamMode::Numeric);
+    let mut computer = Computer::load(program.clone());
+    computer.set_input(Some(&mut input));
+    computer.set_output(Some(&mut output));
+    computer.run().await.unwrap();
+    drop(computer);
+    assert_eq!(String::from_utf8(output.into_inner()).unwrap(), "7408802\n");
+
+    let mut input = StreamInput::new(Cursor::new("one\n"), StreamMode::Numeric);
+    let mut computer = Computer::load(program);
+    computer.set_input(Some(&mut input));
+    let error = computer.run().await.unwrap_err();
+    assert_eq!(
+        error.kind(),
+        &ComputerError::InputFailed(IoError::Other("invalid value: \"one\"".to_string()))
+    );
+
+    // Echoes its input until it reads a newline, then outputs 1000
+    let echo = assemble(
+        "
+        loop:   in [c]
+                out [c]
+                eq [c], #10, [t]
+                jf [t], #loop
+                out #1000
+                hlt
+        c:      .data 0
+        t:      .data 0
+        ",
+    )
+    .unwrap();
+    let mut input = StreamInput::new(Cursor::new("hi\nunused\n"), StreamMode::Ascii);
+    let mut output = StreamOutput::new(Vec::new(), StreamMode::Ascii);
+    let mut computer = Computer::load(echo.clone());
+    computer.set_input(Some(&mut input));
+    computer.set_output(Some(&mut output));
+    computer.run().await.unwrap();
+    drop(computer);
+    assert_eq!(
+        String::from_utf8(output.into_inner()).unwrap(),
+        "hi\n1000\n"
+    );
+
+    // A writer that was closed on the other end
+    struct Closed;
+    impl io::Write for Closed {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    let mut input = StreamInput::new(Cursor::new("hi\n"), StreamMode::Ascii);
+    let mut output = StreamOutput::new(Closed, StreamMode::Ascii);
+    let mut computer = Computer::load(echo);
+    computer.set_input(Some(&mut input));
+    computer.set_output(Some(&mut output));
+    let error = computer.run().await.unwrap_err();
+    assert_eq!(error.kind(), &ComputerError::OutputFailed(IoError::Closed));
+}
@@ -0,0 +1,196 @@
+//! The memory of a `Computer`.
+//!
+//! The loaded image is kept in a single vector. Cells beyond it live in fixed size pages that are
+//! allocated on the first write, so a stack far above the image is about as cheap to access as the
+//! image itself. Pages at very large addresses are kept in a map, so a program can still address
+//! any cell without allocating everything below it.
+
+use crate::intcode::{Cell, MemoryWrite, Value};
+use std::collections::HashMap;
+use std::mem;
+
+const PAGE_SIZE: usize = 1024;
+/// Pages with a lower number are kept in a vector, others in a map.
+const DENSE_PAGES: usize = 1 << 16;
+
+type Page<V> = Box<[V]>;
+
+fn new_page<V: Cell>() -> Page<V> {
+    vec![V::default(); PAGE_SIZE].into_boxed_slice()
+}
+
+#[derive(Clone, Debug)]
+pub struct Memory<V: Cell = Value> {
+    image: Vec<V>,
+    /// Pages by number. Cells that lie within the image are never stored here.
+    pages: Vec<Option<Page<V>>>,
+    distant: HashMap<usize, Page<V>>,
+}
+
+impl<V: Cell> From<Vec<V>> for Memory<V> {
+    fn from(image: Vec<V>) -> Self {
+        Memory {
+            image,
+            pages: Vec::new(),
+            distant: HashMap::new(),
+        }
+    }
+}
+
+impl<V: Cell> Memory<V> {
+    pub fn get(&self, pos: usize) -> V {
+        if let Some(value) = self.image.get(pos) {
+            return value.clone();
+        }
+        self.page(pos / PAGE_SIZE)
+            .map_or_else(V::default, |page| page[pos % PAGE_SIZE].clone())
+    }
+
+    pub fn get_mut(&mut self, pos: usize) -> &mut V {
+        if pos < self.image.len() {
+            return &mut self.image[pos];
+        }
+        let number = pos / PAGE_SIZE;
+        let page = if number < DENSE_PAGES {
+            if self.pages.len() <= number {
+                self.pages.resize_with(number + 1, || None);
+            }
+            self.pages[number].get_or_insert_with(new_page)
+        } else {
+            self.distant.entry(number).or_insert_with(new_page)
+        };
+        &mut page[pos % PAGE_SIZE]
+    }
+
+    /// The memory as it was loaded, without any cells that were added later.
+    pub fn base(&self) -> &[V] {
+        &self.image
+    }
+
+    fn page(&self, number: usize) -> Option<&Page<V>> {
+        if number < DENSE_PAGES {
+            self.pages.get(number).and_then(Option::as_ref)
+        } else {
+            self.distant.get(&number)
+        }
+    }
+
+    fn pages(&self) -> impl Iterator<Item = (usize, &Page<V>)> {
+        let mut distant: Vec<_> = self.distant.iter().map(|(n, page)| (*n, page)).collect();
+        distant.sort_by_key(|(number, _)| *number);
+        self.pages
+            .iter()
+            .enumerate()
+            .filter_map(|(number, page)| page.as_ref().map(|page| (number, page)))
+            .chain(distant)
+    }
+
+    /// The number of pages allocated for cells beyond the image.
+    pub fn page_count(&self) -> usize {
+        self.pages.iter().filter(|page| page.is_some()).count() + self.distant.len()
+    }
+
+    /// The cells of the image, followed by the cells of every page that was written to, in order
+    /// of address. Cells in those pages that were never written are zero.
+    pub fn touched_cells(&self) -> impl Iterator<Item = (usize, V)> + '_ {
+        let image_length = self.image.len();
+        let pages = self.pages().flat_map(|(number, page)| {
+            page.iter()
+                .enumerate()
+                .map(move |(offset, value)| (number * PAGE_SIZE + offset, value.clone()))
+        });
+        self.image
+            .iter()
+            .cloned()
+            .enumerate()
+            .chain(pages.filter(move |(address, _)| *address >= image_length))
+    }
+
+    /// The cells that differ, as the writes that would turn `self` into `other`.
+    pub fn diff(&self, other: &Memory<V>) -> Vec<MemoryWrite<V>> {
+        let mut addresses: Vec<usize> = self
+            .touched_cells()
+            .chain(other.touched_cells())
+            .map(|(address, _)| address)
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+        addresses
+            .into_iter()
+            .filter_map(|address| {
+                let (old, new) = (self.get(address), other.get(address));
+                if old != new {
+                    Some(MemoryWrite { address, old, new })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// The approximate number of bytes allocated on the heap, not counting memory the cells
+    /// themselves allocate.
+    pub fn footprint(&self) -> usize {
+        self.image.capacity() * mem::size_of::<V>()
+            + self.pages.capacity() * mem::size_of::<Option<Page<V>>>()
+            + self.distant.capacity() * mem::size_of::<(usize, Page<V>)>()
+            + self.page_count() * PAGE_SIZE * mem::size_of::<V>()
+    }
+}
+
+/// Memories are equal if their images are and every other cell holds the same value, regardless
+/// of which pages are allocated.
+impl<V: Cell> PartialEq for Memory<V> {
+    fn eq(&self, other: &Memory<V>) -> bool {
+        self.image == other.image && self.diff(other).is_empty()
+    }
+}
+
+impl<V: Cell> Eq for Memory<V> {}
+
+#[test]
+fn test_memory() {
+    let mut memory: Memory = Memory::from(vec![1, 2, 3]);
+    *memory.get_mut(1) = 5;
+    *memory.get_mut(2000) = 7;
+    *memory.get_mut(1 << 30) = 9;
+    assert_eq!(
+        (memory.get(1), memory.get(2000), memory.get(1 << 30)),
+        (5, 7, 9)
+    );
+    assert_eq!((memory.get(3), memory.get(1 << 31)), (0, 0));
+    assert_eq!(memory.base(), &[1, 5, 3]);
+    assert_eq!(memory.page_count(), 2);
+    assert!(memory.footprint() >= 2 * PAGE_SIZE * mem::size_of::<Value>());
+
+    let touched: Vec<_> = memory
+        .touched_cells()
+        .filter(|(_, value)| *value != 0)
+        .collect();
+    assert_eq!(
+        touched,
+        vec![(0, 1), (1, 5), (2, 3), (2000, 7), (1 << 30, 9)]
+    );
+
+    let mut other: Memory = Memory::from(vec![1, 2, 3]);
+    *other.get_mut(2000) = 7;
+    *other.get_mut(5000) = 0;
+    assert_eq!(
+        other.diff(&memory),
+        vec![
+            MemoryWrite {
+                address: 1,
+                old: 2,
+                new: 5
+            },
+            MemoryWrite {
+                address: 1 << 30,
+                old: 0,
+                new: 9
+            },
+        ]
+    );
+    *other.get_mut(1) = 5;
+    *other.get_mut(1 << 30) = 9;
+    assert_eq!(other, memory);
+}
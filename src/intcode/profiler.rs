@@ -0,0 +1,323 @@
+use crate::intcode::{disassemble, Memory, OpCode, TraceEntry, Tracer, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// A run of instructions that is only entered at the top and only left at the bottom.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// The address just after the last instruction.
+    pub end: usize,
+    /// How often the block was entered.
+    pub entries: u64,
+    /// How many instructions were executed within the block in total.
+    pub instructions: u64,
+}
+
+/// A jump to an earlier address, or to the same instruction, that was taken at least once.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct BackEdge {
+    pub from: usize,
+    pub to: usize,
+    pub count: u64,
+}
+
+/// The results of profiling a run, with every list ordered from hottest to coldest.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Report {
+    pub instructions: u64,
+    pub op_codes: Vec<(OpCode, u64)>,
+    pub blocks: Vec<BasicBlock>,
+    pub back_edges: Vec<BackEdge>,
+    pub reads: Vec<(usize, u64)>,
+    pub writes: Vec<(usize, u64)>,
+}
+
+/// Number of entries shown per section when a report is displayed.
+const REPORT_LENGTH: usize = 10;
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} instructions executed", self.instructions)?;
+        writeln!(f, "op codes:")?;
+        for (op_code, count) in self.op_codes.iter().take(REPORT_LENGTH) {
+            writeln!(f, "{:>12}  {}", count, op_code.mnemonic())?;
+        }
+        writeln!(f, "hottest blocks:")?;
+        for block in self.blocks.iter().take(REPORT_LENGTH) {
+            writeln!(
+                f,
+                "{:>12}  {}..{} (entered {} times)",
+                block.instructions, block.start, block.end, block.entries
+            )?;
+        }
+        writeln!(f, "loops:")?;
+        for edge in self.back_edges.iter().take(REPORT_LENGTH) {
+            writeln!(f, "{:>12}  {} -> {}", edge.count, edge.from, edge.to)?;
+        }
+        writeln!(f, "most read cells:")?;
+        for (address, count) in self.reads.iter().take(REPORT_LENGTH) {
+            writeln!(f, "{:>12}  [{}]", count, address)?;
+        }
+        writeln!(f, "most written cells:")?;
+        for (address, count) in self.writes.iter().take(REPORT_LENGTH) {
+            writeln!(f, "{:>12}  [{}]", count, address)?;
+        }
+        Ok(())
+    }
+}
+
+fn hottest_first<T: Ord + Copy>(counts: &HashMap<T, u64>) -> Vec<(T, u64)> {
+    let mut counts: Vec<_> = counts.iter().map(|(key, count)| (*key, *count)).collect();
+    counts
+        .sort_by(|(a_key, a_count), (b_key, b_count)| b_count.cmp(a_count).then(a_key.cmp(b_key)));
+    counts
+}
+
+/// Collects execution statistics. Install it as the tracer of a `Computer`.
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    executions: HashMap<usize, u64>,
+    instructions: HashMap<usize, (OpCode, usize)>,
+    op_codes: HashMap<OpCode, u64>,
+    reads: HashMap<usize, u64>,
+    writes: HashMap<usize, u64>,
+    /// Non-sequential transfers of control between instructions.
+    jumps: HashMap<(usize, usize), u64>,
+    /// The address and size of the previous instruction.
+    previous: Option<(usize, usize)>,
+}
+
+impl Tracer for Profiler {
+    fn trace(&mut self, entry: &TraceEntry) {
+        let address = entry.step.address;
+        let size = 1 + entry.parameters.len();
+        *self.executions.entry(address).or_insert(0) += 1;
+        self.instructions
+            .insert(address, (entry.step.op_code, size));
+        *self.op_codes.entry(entry.step.op_code).or_insert(0) += 1;
+        for read in &entry.step.reads {
+            *self.reads.entry(read.address).or_insert(0) += 1;
+        }
+        if let Some(write) = entry.step.write {
+            *self.writes.entry(write.address).or_insert(0) += 1;
+        }
+        if let Some((previous, previous_size)) = self.previous {
+            if previous + previous_size != address {
+                *self.jumps.entry((previous, address)).or_insert(0) += 1;
+            }
+        }
+        self.previous = Some((address, size));
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    pub fn executions(&self, address: usize) -> u64 {
+        self.executions.get(&address).copied().unwrap_or(0)
+    }
+
+    pub fn op_code_executions(&self, op_code: OpCode) -> u64 {
+        self.op_codes.get(&op_code).copied().unwrap_or(0)
+    }
+
+    pub fn reads(&self, address: usize) -> u64 {
+        self.reads.get(&address).copied().unwrap_or(0)
+    }
+
+    pub fn writes(&self, address: usize) -> u64 {
+        self.writes.get(&address).copied().unwrap_or(0)
+    }
+
+    /// Split the executed instructions into basic blocks, in order of address.
+    fn blocks(&self) -> Vec<BasicBlock> {
+        let is_jump =
+            |op_code| [OpCode::JumpIfTrue, OpCode::JumpIfFalse, OpCode::Quit].contains(&op_code);
+        let mut leaders: HashSet<usize> = self.jumps.keys().map(|(_, to)| *to).collect();
+        for (address, (op_code, size)) in &self.instructions {
+            if is_jump(*op_code) {
+                leaders.insert(address + size);
+            }
+        }
+        let mut addresses: Vec<_> = self.instructions.keys().copied().collect();
+        addresses.sort();
+
+        let mut blocks: Vec<BasicBlock> = Vec::new();
+        let mut block_open = false;
+        for address in addresses {
+            let (op_code, size) = self.instructions[&address];
+            let executions = self.executions(address);
+            match blocks.last_mut() {
+                Some(block)
+                    if block_open && block.end == address && !leaders.contains(&address) =>
+                {
+                    block.end += size;
+                    block.instructions += executions;
+                }
+                _ => blocks.push(BasicBlock {
+                    start: address,
+                    end: address + size,
+                    entries: executions,
+                    instructions: executions,
+                }),
+            }
+            block_open = !is_jump(op_code);
+        }
+        blocks
+    }
+
+    pub fn report(&self) -> Report {
+        let mut blocks = self.blocks();
+        blocks.sort_by(|a, b| {
+            b.instructions
+                .cmp(&a.instructions)
+                .then(a.start.cmp(&b.start))
+        });
+        let back_edges = hottest_first(&self.jumps)
+            .into_iter()
+            .filter(|((from, to), _)| to <= from)
+            .map(|((from, to), count)| BackEdge { from, to, count })
+            .collect();
+        let mut op_codes: Vec<_> = self
+            .op_codes
+            .iter()
+            .map(|(op_code, count)| (*op_code, *count))
+            .collect();
+        op_codes.sort_by(|(a_op_code, a_count), (b_op_code, b_count)| {
+            b_count
+                .cmp(a_count)
+                .then(Value::from(*a_op_code).cmp(&Value::from(*b_op_code)))
+        });
+        Report {
+            instructions: self.executions.values().sum(),
+            op_codes,
+            blocks,
+            back_edges,
+            reads: hottest_first(&self.reads),
+            writes: hottest_first(&self.writes),
+        }
+    }
+
+    /// The disassembly of `memory`, with the number of executions in front of every line.
+    pub fn annotate<'a>(&'a self, memory: &'a Memory) -> AnnotatedDisassembly<'a> {
+        AnnotatedDisassembly {
+            profiler: self,
+            memory,
+        }
+    }
+}
+
+pub struct AnnotatedDisassembly<'a> {
+    profiler: &'a Profiler,
+    memory: &'a Memory,
+}
+
+impl fmt::Display for AnnotatedDisassembly<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in disassemble(self.memory).items() {
+            match self.profiler.executions(item.address()) {
+                0 => write!(f, "{:>12}", "")?,
+                count => write!(f, "{:>12}", count)?,
+            }
+            writeln!(f, "  {:>5}: {}", item.address(), item)?;
+        }
+        Ok(())
+    }
+}
+
+#[futures_await_test::async_test]
+async fn test_profiler() {
+    use crate::intcode::{assemble, Computer};
+    let program = assemble(
+        "
+                add #3, #0, [counter]
+        loop:   out [counter]
+                add [counter], #-1, [counter]
+                jt [counter], #loop
+                rbo #5
+                hlt
+        counter: .data 0
+        ",
+    )
+    .unwrap();
+    let mut profiler = Profiler::new();
+    let mut computer = Computer::load(program.clone());
+    computer.set_tracer(Some(&mut profiler));
+    computer.run().await.unwrap();
+    drop(computer);
+
+    assert_eq!(profiler.executions(4), 3);
+    assert_eq!(profiler.op_code_executions(OpCode::Add), 4);
+    assert_eq!((profiler.reads(16), profiler.writes(16)), (9, 4));
+    let report = profiler.report();
+    assert_eq!(report.instructions, 12);
+    assert_eq!(
+        report.blocks,
+        vec![
+            BasicBlock {
+                start: 4,
+                end: 13,
+                entries: 3,
+                instructions: 9
+            },
+            BasicBlock {
+                start: 13,
+                end: 16,
+                entries: 1,
+                instructions: 2
+            },
+            BasicBlock {
+                start: 0,
+                end: 4,
+                entries: 1,
+                instructions: 1
+            },
+        ]
+    );
+    assert_eq!(
+        report.back_edges,
+        vec![BackEdge {
+            from: 10,
+            to: 4,
+            count: 2
+        }]
+    );
+    assert!(profiler
+        .annotate(&program)
+        .to_string()
+        .starts_with("           1      0: add #3, #0, [16]\n           3      4: out [16]\n"));
+}
+
+#[futures_await_test::async_test]
+async fn test_self_loop() {
+    use crate::intcode::{parse_program, Computer};
+    // Jumps to itself forever
+    let mut profiler = Profiler::new();
+    let mut computer = Computer::load(parse_program("1105,1,0").unwrap());
+    computer.set_tracer(Some(&mut profiler));
+    for _ in 0..10 {
+        computer.step().await.unwrap();
+    }
+    drop(computer);
+    let report = profiler.report();
+    assert_eq!(
+        report.back_edges,
+        vec![BackEdge {
+            from: 0,
+            to: 0,
+            count: 9
+        }]
+    );
+    assert_eq!(
+        report.blocks,
+        vec![BasicBlock {
+            start: 0,
+            end: 3,
+            entries: 10,
+            instructions: 10
+        }]
+    );
+}
@@ -0,0 +1,129 @@
+//! Programs shared by the tests of the engines, and a way to run them.
+
+use crate::intcode::{
+    assemble, parse_program, Computer, ComputerError, Engine, Memory, Status, TraceEntry, Value,
+};
+use std::fs;
+
+/// Gives the next input, from the outputs so far.
+pub type Input = fn(&[Value]) -> Value;
+
+/// The puzzle input of a day, such as `"day9"`.
+pub fn load(day: &str) -> Memory {
+    parse_program(&fs::read_to_string(format!("input/{}", day)).unwrap()).unwrap()
+}
+
+/// The gravity assist program of day 2, with the noun and verb of the first part.
+pub fn day_2() -> Memory {
+    let mut memory = load("day2");
+    *memory.get_mut(1) = 12;
+    *memory.get_mut(2) = 2;
+    memory
+}
+
+/// The arcade cabinet of day 13, set to play for free.
+pub fn arcade() -> Memory {
+    let mut memory = load("day13");
+    *memory.get_mut(0) = 2;
+    memory
+}
+
+/// Plays the arcade game by moving the paddle towards the ball.
+pub fn paddle(outputs: &[Value]) -> Value {
+    let x_of = |tile| {
+        outputs
+            .chunks(3)
+            .rev()
+            .find(|chunk| chunk[0] >= 0 && chunk[2] == tile)
+            .map_or(0, |chunk| chunk[0])
+    };
+    (x_of(4) - x_of(3)).signum()
+}
+
+/// Outputs 1 and 2, by incrementing the parameter of its own output instruction.
+pub fn self_modifying() -> Memory {
+    assemble(
+        "
+        loop:   out #1
+                add [loop+1], #1, [loop+1]
+                add [count], #-1, [count]
+                jt [count], #loop
+                hlt
+        count:  .data 2
+        ",
+    )
+    .unwrap()
+}
+
+/// Outputs 2, by patching the instruction that follows within the same basic block.
+pub fn patches_next() -> Memory {
+    assemble(
+        "
+                add #2, #0, [patch+1]
+        patch:  add #1, #0, [out+1]
+        out:    out #0
+                hlt
+        ",
+    )
+    .unwrap()
+}
+
+/// Programs that every engine has to run the same way, with their input.
+pub fn programs() -> Vec<(Memory, Input)> {
+    vec![
+        (self_modifying(), |_| 0),
+        (patches_next(), |_| 0),
+        (parse_program("109,-10,1201,0,3,0,99").unwrap(), |_| 0),
+        (day_2(), |_| 0),
+        (load("day5"), |_| 5),
+        (load("day9"), |_| 2),
+        (arcade(), paddle),
+    ]
+}
+
+/// Everything an engine shows of a run.
+#[derive(PartialEq, Debug)]
+pub struct Run {
+    pub result: Result<Vec<Value>, ComputerError>,
+    pub memory: Memory,
+    pub instruction_pointer: usize,
+    pub relative_base: Value,
+    pub executed_instructions: u64,
+    pub trace: Vec<TraceEntry>,
+}
+
+/// Run until the program halts or fails. With `traced`, every instruction is recorded, which
+/// leaves out compilation.
+pub fn run_with(engine: Engine, memory: Memory, input: Input, traced: bool) -> Run {
+    let mut trace = Vec::new();
+    let mut tracer = |entry: &TraceEntry| trace.push(entry.clone());
+    let mut computer = Computer::load(memory);
+    computer.set_engine(engine);
+    if traced {
+        computer.set_tracer(Some(&mut tracer));
+    }
+    let mut outputs = Vec::new();
+    let result = loop {
+        match computer.resume() {
+            Ok(Status::NeedsInput) => computer.provide_input(input(&outputs)),
+            Ok(Status::Output(value)) => outputs.push(value),
+            Ok(Status::Halted) => break Ok(outputs),
+            Err(error) => break Err(error),
+        }
+    };
+    let (memory, instruction_pointer, relative_base, executed_instructions) = (
+        computer.memory().clone(),
+        computer.instruction_pointer(),
+        computer.relative_base(),
+        computer.executed_instructions(),
+    );
+    drop(computer);
+    Run {
+        result,
+        memory,
+        instruction_pointer,
+        relative_base,
+        executed_instructions,
+        trace,
+    }
+}
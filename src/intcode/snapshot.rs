@@ -0,0 +1,316 @@
+//! Saving and restoring the state of a running `Computer`.
+//!
+//! A snapshot is written as text, with one field per line:
+//!
+//! ```text
+//! instruction_pointer 4
+//! relative_base 0
+//! executed_instructions 1
+//! outputs 0
+//! halted false
+//! pending_input 1,2
+//! memory 1101,4,5,100,4,100,99
+//! additional 100:9
+//! ```
+
+use crate::intcode::{Computer, History, Memory, Value};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::*;
+
+/// The complete state of a `Computer`, without the input and output it is connected to.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Snapshot {
+    memory: Memory,
+    instruction_pointer: usize,
+    relative_base: Value,
+    executed_instructions: u64,
+    outputs: u64,
+    halted: bool,
+    pending_input: VecDeque<Value>,
+}
+
+impl Snapshot {
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    pub fn relative_base(&self) -> Value {
+        self.relative_base
+    }
+
+    pub fn executed_instructions(&self) -> u64 {
+        self.executed_instructions
+    }
+}
+
+impl<'a> Computer<'a> {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.clone(),
+            instruction_pointer: self.instruction_pointer,
+            relative_base: self.relative_base,
+            executed_instructions: self.executed_instructions,
+            outputs: self.outputs,
+            halted: self.halted,
+            pending_input: self.pending_input.clone(),
+        }
+    }
+
+    /// Restore the state from a snapshot, keeping the current input and output. History that is
+    /// being recorded and a code map start again from the restored state.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.memory = snapshot.memory;
+        self.decoded.clear();
+        self.compiler.clear();
+        self.instruction_pointer = snapshot.instruction_pointer;
+        self.relative_base = snapshot.relative_base;
+        self.executed_instructions = snapshot.executed_instructions;
+        self.outputs = snapshot.outputs;
+        self.halted = snapshot.halted;
+        self.pending_input = snapshot.pending_input;
+        self.clear_code_map();
+        if let Some(history) = &self.history {
+            self.set_history(Some(history.limits()));
+        }
+    }
+
+    /// Create a copy of this computer that is not connected to any input or output. The copy
+    /// keeps the engine, limits and self-modification policy, and records history and a code map
+    /// from the copied state if this computer does.
+    pub fn fork<'b>(&self) -> Computer<'b> {
+        let mut computer = Computer::from(self.snapshot());
+        computer.set_engine(self.engine);
+        computer.set_limits(self.limits);
+        computer.set_self_modification(self.self_modification);
+        computer.set_history(self.history.as_ref().map(History::limits));
+        computer
+    }
+}
+
+impl From<Snapshot> for Computer<'_> {
+    fn from(snapshot: Snapshot) -> Self {
+        let mut computer = Computer::load(Memory::from(Vec::new()));
+        computer.restore(snapshot);
+        computer
+    }
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum ParseSnapshotError {
+    #[error("missing field: {0}")]
+    MissingField(&'static str),
+    #[error("invalid value for field {0}")]
+    InvalidField(&'static str),
+    #[error("unknown field: {0}")]
+    UnknownField(String),
+    #[error("duplicate field: {0}")]
+    DuplicateField(String),
+}
+
+fn write_list<T: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    name: &str,
+    values: impl IntoIterator<Item = T>,
+) -> fmt::Result {
+    write!(f, "{}", name)?;
+    for (i, value) in values.into_iter().enumerate() {
+        write!(f, "{}{}", if i == 0 { " " } else { "," }, value)?;
+    }
+    writeln!(f)
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "instruction_pointer {}", self.instruction_pointer)?;
+        writeln!(f, "relative_base {}", self.relative_base)?;
+        writeln!(f, "executed_instructions {}", self.executed_instructions)?;
+        writeln!(f, "outputs {}", self.outputs)?;
+        writeln!(f, "halted {}", self.halted)?;
+        write_list(f, "pending_input", &self.pending_input)?;
+        write_list(f, "memory", self.memory.base())?;
+        write_list(
+            f,
+            "additional",
+            self.memory
+                .touched_cells()
+                .skip(self.memory.base().len())
+                .filter(|(_, value)| *value != 0)
+                .map(|(address, value)| format!("{}:{}", address, value)),
+        )
+    }
+}
+
+fn parse_list<T: FromStr>(field: &'static str, s: &str) -> Result<Vec<T>, ParseSnapshotError> {
+    s.split(',')
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            item.parse()
+                .map_err(|_| ParseSnapshotError::InvalidField(field))
+        })
+        .collect()
+}
+
+impl FromStr for Snapshot {
+    type Err = ParseSnapshotError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = HashMap::new();
+        for line in s.lines().filter(|line| !line.trim().is_empty()) {
+            let mut parts = line.trim().splitn(2, ' ');
+            let name = parts.next().unwrap_or_default();
+            let value = parts.next().unwrap_or_default().trim();
+            if fields.insert(name, value).is_some() {
+                return Err(ParseSnapshotError::DuplicateField(name.to_string()));
+            }
+        }
+        let mut field = |name: &'static str| {
+            fields
+                .remove(name)
+                .ok_or(ParseSnapshotError::MissingField(name))
+        };
+        let instruction_pointer = field("instruction_pointer")?
+            .parse()
+            .map_err(|_| ParseSnapshotError::InvalidField("instruction_pointer"))?;
+        let relative_base = field("relative_base")?
+            .parse()
+            .map_err(|_| ParseSnapshotError::InvalidField("relative_base"))?;
+        let executed_instructions = field("executed_instructions")?
+            .parse()
+            .map_err(|_| ParseSnapshotError::InvalidField("executed_instructions"))?;
+        let outputs = field("outputs")?
+            .parse()
+            .map_err(|_| ParseSnapshotError::InvalidField("outputs"))?;
+        let halted = field("halted")?
+            .parse()
+            .map_err(|_| ParseSnapshotError::InvalidField("halted"))?;
+        let pending_input = parse_list("pending_input", field("pending_input")?)?
+            .into_iter()
+            .collect();
+        let mut memory = Memory::from(parse_list("memory", field("memory")?)?);
+        for cell in parse_list::<String>("additional", field("additional")?)? {
+            let mut parts = cell.splitn(2, ':');
+            let address = parts.next().and_then(|address| address.parse().ok());
+            let value = parts.next().and_then(|value| value.parse().ok());
+            match (address, value) {
+                (Some(address), Some(value)) => {
+                    *memory.get_mut(address) = value;
+                }
+                _ => return Err(ParseSnapshotError::InvalidField("additional")),
+            }
+        }
+        if let Some(name) = fields.keys().next() {
+            return Err(ParseSnapshotError::UnknownField(name.to_string()));
+        }
+        Ok(Snapshot {
+            memory,
+            instruction_pointer,
+            relative_base,
+            executed_instructions,
+            outputs,
+            halted,
+            pending_input,
+        })
+    }
+}
+
+#[test]
+fn test_serialize_snapshot() {
+    use crate::intcode::{parse_program, Limits, Status};
+    let mut computer = Computer::load(parse_program("1101,4,5,100,3,101,4,101,99").unwrap());
+    assert_eq!(computer.resume(), Ok(Status::NeedsInput));
+    computer.provide_input(7);
+    let snapshot = computer.snapshot();
+    let text = snapshot.to_string();
+    assert_eq!(
+        text,
+        "instruction_pointer 4\nrelative_base 0\nexecuted_instructions 1\noutputs 0\n\
+         halted false\npending_input 7\nmemory 1101,4,5,100,3,101,4,101,99\nadditional 100:9\n"
+    );
+    let restored = text.parse::<Snapshot>().unwrap();
+    assert_eq!(restored, snapshot);
+
+    let mut computer = Computer::from(restored);
+    assert_eq!(computer.resume(), Ok(Status::Output(7)));
+    assert_eq!(computer.resume(), Ok(Status::Halted));
+    assert_eq!(computer.executed_instructions(), 4);
+    // Restoring resets the counters the limits are checked against
+    computer.restore(snapshot.clone());
+    assert_eq!(computer.executed_instructions(), 1);
+    assert_eq!(computer.snapshot(), snapshot);
+    computer.set_limits(Limits {
+        outputs: Some(1),
+        ..Limits::default()
+    });
+    assert_eq!(computer.resume(), Ok(Status::Output(7)));
+    assert_eq!(
+        "relative_base 0".parse::<Snapshot>(),
+        Err(ParseSnapshotError::MissingField("instruction_pointer"))
+    );
+    assert_eq!(
+        format!("{}outputs 1\n", text).parse::<Snapshot>(),
+        Err(ParseSnapshotError::DuplicateField("outputs".to_string()))
+    );
+}
+
+#[test]
+fn test_fork_keeps_settings() {
+    use crate::intcode::{
+        parse_program, ComputerError, Engine, HistoryLimits, Limits, SelfModification, Status,
+    };
+    let mut computer = Computer::load(parse_program("1101,1,1,0,1105,1,0").unwrap());
+    computer.set_engine(Engine::Compiled);
+    computer.set_limits(Limits {
+        instructions: Some(3),
+        ..Limits::default()
+    });
+    computer.set_self_modification(SelfModification::Report);
+    computer.set_history(Some(HistoryLimits::default()));
+    let mut fork = computer.fork();
+    let error = fork.resume().unwrap_err();
+    assert_eq!(error.kind(), &ComputerError::InstructionLimitExceeded(3));
+    assert!(fork.code_map().unwrap().is_written(0));
+    assert_eq!(fork.history().unwrap().limits(), HistoryLimits::default());
+    assert!(fork.reverse_step().is_some());
+
+    let mut fork = Computer::load(parse_program("99").unwrap()).fork();
+    assert_eq!(fork.resume(), Ok(Status::Halted));
+    assert!(fork.code_map().is_none());
+    assert!(fork.history().is_none());
+}
+
+#[test]
+fn test_branching_search() {
+    use crate::intcode::{parse_program, Status};
+    use std::collections::HashSet;
+    use std::fs;
+    // Find the shortest path to the oxygen system of day 15 by forking the droid at every step,
+    // instead of walking back to the start.
+    let droid = Computer::load(parse_program(&fs::read_to_string("input/day15").unwrap()).unwrap());
+    let mut visited = HashSet::new();
+    visited.insert((0, 0));
+    let mut queue = VecDeque::new();
+    queue.push_back((droid.snapshot(), (0, 0), 0));
+    let distance = 'search: loop {
+        let (snapshot, (x, y), distance) = queue.pop_front().unwrap();
+        for &(command, dx, dy) in &[(1, 0, -1), (2, 0, 1), (3, -1, 0), (4, 1, 0)] {
+            let position = (x + dx, y + dy);
+            if !visited.insert(position) {
+                continue;
+            }
+            let mut droid = Computer::from(snapshot.clone());
+            droid.provide_input(command);
+            match droid.resume().unwrap() {
+                Status::Output(0) => {}
+                Status::Output(1) => queue.push_back((droid.snapshot(), position, distance + 1)),
+                Status::Output(2) => break 'search distance + 1,
+                other => panic!("unexpected status: {:?}", other),
+            }
+        }
+    };
+    assert_eq!(distance, 228);
+}
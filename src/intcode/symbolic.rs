@@ -0,0 +1,513 @@
+//! Symbolic execution of Intcode programs.
+//!
+//! Memory cells and inputs can be replaced by named symbols. Arithmetic on symbols builds
+//! expression trees instead of values, so after a run the outputs and memory describe how they
+//! depend on the symbols. Execution stops with an error as soon as control flow, an address or
+//! the code itself would depend on a symbol. Linear expressions can then be solved for a target
+//! value.
+
+use crate::intcode::{ComputerError, Instruction, Memory, OpCode, ParameterMode, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+use thiserror::*;
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Expression {
+    Constant(Value),
+    Symbol(Rc<str>),
+    Add(Rc<Expression>, Rc<Expression>),
+    Multiply(Rc<Expression>, Rc<Expression>),
+    LessThan(Rc<Expression>, Rc<Expression>),
+    Equals(Rc<Expression>, Rc<Expression>),
+    /// The value at an address that depends on symbols.
+    Load(Rc<Expression>),
+}
+
+impl Expression {
+    pub fn symbol(name: &str) -> Expression {
+        Expression::Symbol(name.into())
+    }
+
+    pub fn constant(&self) -> Option<Value> {
+        match self {
+            Expression::Constant(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn sum(a: Expression, b: Expression) -> Result<Expression, ComputerError> {
+        Ok(match (a.constant(), b.constant()) {
+            (Some(a), Some(b)) => {
+                Expression::Constant(a.checked_add(b).ok_or(ComputerError::ArithmaticError)?)
+            }
+            (Some(0), _) => b,
+            (_, Some(0)) => a,
+            _ => Expression::Add(Rc::new(a), Rc::new(b)),
+        })
+    }
+
+    pub fn product(a: Expression, b: Expression) -> Result<Expression, ComputerError> {
+        Ok(match (a.constant(), b.constant()) {
+            (Some(a), Some(b)) => {
+                Expression::Constant(a.checked_mul(b).ok_or(ComputerError::ArithmaticError)?)
+            }
+            (Some(0), _) | (_, Some(0)) => Expression::Constant(0),
+            (Some(1), _) => b,
+            (_, Some(1)) => a,
+            _ => Expression::Multiply(Rc::new(a), Rc::new(b)),
+        })
+    }
+
+    pub fn less_than(a: Expression, b: Expression) -> Expression {
+        match (a.constant(), b.constant()) {
+            (Some(a), Some(b)) => Expression::Constant(if a < b { 1 } else { 0 }),
+            _ => Expression::LessThan(Rc::new(a), Rc::new(b)),
+        }
+    }
+
+    pub fn equals(a: Expression, b: Expression) -> Expression {
+        match (a.constant(), b.constant()) {
+            (Some(a), Some(b)) => Expression::Constant(if a == b { 1 } else { 0 }),
+            _ => Expression::Equals(Rc::new(a), Rc::new(b)),
+        }
+    }
+
+    /// The expression as a sum of symbols with constant factors, if it is one.
+    pub fn linear(&self) -> Option<Linear> {
+        match self {
+            Expression::Constant(value) => Some(Linear {
+                constant: *value,
+                terms: BTreeMap::new(),
+            }),
+            Expression::Symbol(name) => Some(Linear {
+                constant: 0,
+                terms: vec![(name.to_string(), 1)].into_iter().collect(),
+            }),
+            Expression::Add(a, b) => a.linear()?.add(&b.linear()?),
+            Expression::Multiply(a, b) => match (a.constant(), b.constant()) {
+                (Some(factor), _) => b.linear()?.scale(factor),
+                (_, Some(factor)) => a.linear()?.scale(factor),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl From<Value> for Expression {
+    fn from(value: Value) -> Self {
+        Expression::Constant(value)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Constant(value) => write!(f, "{}", value),
+            Expression::Symbol(name) => write!(f, "{}", name),
+            Expression::Add(a, b) => write!(f, "({} + {})", a, b),
+            Expression::Multiply(a, b) => write!(f, "({} * {})", a, b),
+            Expression::LessThan(a, b) => write!(f, "({} < {})", a, b),
+            Expression::Equals(a, b) => write!(f, "({} == {})", a, b),
+            Expression::Load(address) => write!(f, "[{}]", address),
+        }
+    }
+}
+
+/// `constant + factor * symbol + ...`
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Linear {
+    pub constant: Value,
+    /// The factor of every symbol, none of which are zero.
+    pub terms: BTreeMap<String, Value>,
+}
+
+impl Linear {
+    fn add(mut self, other: &Linear) -> Option<Linear> {
+        self.constant = self.constant.checked_add(other.constant)?;
+        for (name, factor) in &other.terms {
+            let sum = self.terms.get(name).unwrap_or(&0).checked_add(*factor)?;
+            if sum == 0 {
+                self.terms.remove(name);
+            } else {
+                self.terms.insert(name.clone(), sum);
+            }
+        }
+        Some(self)
+    }
+
+    fn scale(mut self, by: Value) -> Option<Linear> {
+        if by == 0 {
+            self.terms.clear();
+        }
+        self.constant = self.constant.checked_mul(by)?;
+        for factor in self.terms.values_mut() {
+            *factor = factor.checked_mul(by)?;
+        }
+        Some(self)
+    }
+
+    /// Find values for the symbols within their `bounds` for which the expression equals
+    /// `target`. Every symbol needs bounds, and the one with the largest factor is assigned first.
+    pub fn solve(
+        &self,
+        target: Value,
+        bounds: &HashMap<&str, RangeInclusive<Value>>,
+    ) -> Option<BTreeMap<String, Value>> {
+        let mut terms = Vec::new();
+        for (name, factor) in &self.terms {
+            terms.push((name.as_str(), *factor, bounds.get(name.as_str())?.clone()));
+        }
+        terms.sort_by_key(|(_, factor, _)| std::cmp::Reverse(factor.checked_abs()));
+        let mut solution = BTreeMap::new();
+        if solve_terms(&terms, target.checked_sub(self.constant)?, &mut solution)? {
+            Some(solution)
+        } else {
+            None
+        }
+    }
+}
+
+/// Assign the first of `terms` every value that the remaining terms can make up the difference
+/// for. `None` if the bounds are too large to compute with.
+fn solve_terms(
+    terms: &[(&str, Value, RangeInclusive<Value>)],
+    target: Value,
+    solution: &mut BTreeMap<String, Value>,
+) -> Option<bool> {
+    let ((name, factor, bounds), rest) = match terms.split_first() {
+        Some(split) => split,
+        None => return Some(target == 0),
+    };
+    // The range of values the remaining terms can sum up to
+    let (mut low, mut high): (Value, Value) = (0, 0);
+    for (_, factor, bounds) in rest {
+        let a = factor.checked_mul(*bounds.start())?;
+        let b = factor.checked_mul(*bounds.end())?;
+        low = low.checked_add(a.min(b))?;
+        high = high.checked_add(a.max(b))?;
+    }
+    // factor * value has to lie within target - high..=target - low
+    let (low, high) = (target.checked_sub(high)?, target.checked_sub(low)?);
+    let (first, last) = if *factor > 0 {
+        (div_ceil(low, *factor), div_floor(high, *factor))
+    } else {
+        (div_ceil(high, *factor), div_floor(low, *factor))
+    };
+    for value in first.max(*bounds.start())..=last.min(*bounds.end()) {
+        if solve_terms(
+            rest,
+            target.checked_sub(factor.checked_mul(value)?)?,
+            solution,
+        )? {
+            solution.insert(name.to_string(), value);
+            return Some(true);
+        }
+    }
+    Some(false)
+}
+
+fn div_floor(a: Value, b: Value) -> Value {
+    let quotient = a / b;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        quotient - 1
+    } else {
+        quotient
+    }
+}
+
+fn div_ceil(a: Value, b: Value) -> Value {
+    let quotient = a / b;
+    if a % b != 0 && (a < 0) == (b < 0) {
+        quotient + 1
+    } else {
+        quotient
+    }
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum SymbolicError {
+    #[error("the branch at {address} depends on {condition}")]
+    SymbolicBranch {
+        address: usize,
+        condition: Expression,
+    },
+    #[error("the instruction at {address} depends on {expression}")]
+    SymbolicInstruction {
+        address: usize,
+        expression: Expression,
+    },
+    #[error("the instruction at {address} accesses the address {expression}")]
+    SymbolicAddress {
+        address: usize,
+        expression: Expression,
+    },
+    #[error("the instruction at {address} failed: {error}")]
+    Computer {
+        address: usize,
+        #[source]
+        error: ComputerError,
+    },
+}
+
+/// Executes a program on values that may be symbolic.
+#[derive(Clone, Debug)]
+pub struct SymbolicComputer {
+    memory: Memory,
+    /// The cells that hold a symbolic value, which takes precedence over `memory`.
+    symbolic: HashMap<usize, Expression>,
+    instruction_pointer: usize,
+    relative_base: Value,
+    input: VecDeque<Expression>,
+    output: Vec<Expression>,
+}
+
+impl SymbolicComputer {
+    pub fn load(memory: Memory) -> Self {
+        SymbolicComputer {
+            memory,
+            symbolic: HashMap::new(),
+            instruction_pointer: 0,
+            relative_base: 0,
+            input: VecDeque::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Replace the value at `address` by a symbol.
+    pub fn set_symbol(&mut self, address: usize, name: &str) {
+        self.symbolic.insert(address, Expression::symbol(name));
+    }
+
+    /// Queue an input, which may be a symbol.
+    pub fn provide_input(&mut self, value: impl Into<Expression>) {
+        self.input.push_back(value.into());
+    }
+
+    pub fn get(&self, address: usize) -> Expression {
+        match self.symbolic.get(&address) {
+            Some(expression) => expression.clone(),
+            None => Expression::Constant(self.memory.get(address)),
+        }
+    }
+
+    pub fn output(&self) -> &[Expression] {
+        &self.output
+    }
+
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    /// Run until the program halts, or fails on a symbolic value.
+    pub fn run(&mut self) -> Result<(), SymbolicError> {
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Execute one instruction, returning `false` once the program has halted.
+    pub fn step(&mut self) -> Result<bool, SymbolicError> {
+        let address = self.instruction_pointer;
+        let fail = |error| SymbolicError::Computer { address, error };
+        let instruction = match self.get(address) {
+            Expression::Constant(value) => Instruction(value),
+            expression => {
+                return Err(SymbolicError::SymbolicInstruction {
+                    address,
+                    expression,
+                })
+            }
+        };
+        let op_code = instruction.op_code().map_err(fail)?;
+        let mut parameters = Vec::with_capacity(op_code.parameter_count());
+        for (i, mode) in instruction
+            .modes()
+            .take(op_code.parameter_count())
+            .enumerate()
+        {
+            parameters.push((mode.map_err(fail)?, self.get(address + 1 + i)));
+        }
+        self.instruction_pointer = address + 1 + parameters.len();
+        match op_code {
+            OpCode::Quit => {
+                self.instruction_pointer = address;
+                return Ok(false);
+            }
+            OpCode::Add | OpCode::Multiply | OpCode::LessThan | OpCode::Equals => {
+                let a = self.read(address, &parameters[0])?;
+                let b = self.read(address, &parameters[1])?;
+                let value = match op_code {
+                    OpCode::Add => Expression::sum(a, b).map_err(fail)?,
+                    OpCode::Multiply => Expression::product(a, b).map_err(fail)?,
+                    OpCode::LessThan => Expression::less_than(a, b),
+                    _ => Expression::equals(a, b),
+                };
+                self.write(address, &parameters[2], value)?;
+            }
+            OpCode::Input => {
+                let value = self
+                    .input
+                    .pop_front()
+                    .ok_or_else(|| fail(ComputerError::ReadInputError))?;
+                self.write(address, &parameters[0], value)?;
+            }
+            OpCode::Output => {
+                let value = self.read(address, &parameters[0])?;
+                self.output.push(value);
+            }
+            OpCode::JumpIfTrue | OpCode::JumpIfFalse => {
+                let condition = self.read(address, &parameters[0])?;
+                let condition = condition
+                    .constant()
+                    .ok_or(SymbolicError::SymbolicBranch { address, condition })?;
+                if (condition != 0) == (op_code == OpCode::JumpIfTrue) {
+                    let to = self.read(address, &parameters[1])?;
+                    let to = self.concrete(address, to)?;
+                    self.instruction_pointer =
+                        usize::try_from(to).map_err(|_| fail(ComputerError::InvalidJump))?;
+                }
+            }
+            OpCode::RelativeBaseOffset => {
+                let offset = self.read(address, &parameters[0])?;
+                self.relative_base += self.concrete(address, offset)?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn concrete(&self, address: usize, expression: Expression) -> Result<Value, SymbolicError> {
+        expression.constant().ok_or(SymbolicError::SymbolicAddress {
+            address,
+            expression,
+        })
+    }
+
+    /// The address a parameter refers to, or the symbolic expression for it.
+    fn address_of(
+        &self,
+        address: usize,
+        (mode, value): &(ParameterMode, Expression),
+    ) -> Result<Result<usize, Expression>, SymbolicError> {
+        let value = match (mode, value.constant()) {
+            (ParameterMode::Position, Some(value)) => value,
+            (ParameterMode::Relative, Some(value)) => self.relative_base + value,
+            (ParameterMode::Relative, None) => {
+                let base = Expression::Constant(self.relative_base);
+                return Ok(Err(Expression::sum(base, value.clone())
+                    .map_err(|error| SymbolicError::Computer { address, error })?));
+            }
+            _ => return Ok(Err(value.clone())),
+        };
+        usize::try_from(value)
+            .map(Ok)
+            .map_err(|_| SymbolicError::Computer {
+                address,
+                error: ComputerError::ReadOutsideOfMemory,
+            })
+    }
+
+    fn read(
+        &self,
+        address: usize,
+        parameter: &(ParameterMode, Expression),
+    ) -> Result<Expression, SymbolicError> {
+        if parameter.0 == ParameterMode::Immediate {
+            return Ok(parameter.1.clone());
+        }
+        Ok(match self.address_of(address, parameter)? {
+            Ok(from) => self.get(from),
+            Err(from) => Expression::Load(Rc::new(from)),
+        })
+    }
+
+    fn write(
+        &mut self,
+        address: usize,
+        parameter: &(ParameterMode, Expression),
+        value: Expression,
+    ) -> Result<(), SymbolicError> {
+        if parameter.0 == ParameterMode::Immediate {
+            return Err(SymbolicError::Computer {
+                address,
+                error: ComputerError::WriteInImmediateMode,
+            });
+        }
+        let to = match self.address_of(address, parameter)? {
+            Ok(to) => to,
+            Err(expression) => {
+                return Err(SymbolicError::SymbolicAddress {
+                    address,
+                    expression,
+                })
+            }
+        };
+        match value {
+            Expression::Constant(value) => {
+                self.symbolic.remove(&to);
+                *self.memory.get_mut(to) = value;
+            }
+            value => {
+                self.symbolic.insert(to, value);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_symbolic_day_2() {
+    use crate::intcode::parse_program;
+    use std::fs;
+    let program = parse_program(&fs::read_to_string("input/day2").unwrap()).unwrap();
+    let mut computer = SymbolicComputer::load(program);
+    computer.set_symbol(1, "noun");
+    computer.set_symbol(2, "verb");
+    computer.run().unwrap();
+    let linear = computer.get(0).linear().unwrap();
+    assert_eq!(linear.terms.len(), 2);
+    assert_eq!(linear.terms["verb"], 1);
+    let bounds = vec![("noun", 0..=99), ("verb", 0..=99)]
+        .into_iter()
+        .collect();
+    let solution = linear.solve(19_690_720, &bounds).unwrap();
+    assert_eq!((solution["noun"], solution["verb"]), (60, 86));
+    assert_eq!(linear.solve(-1, &bounds), None);
+}
+
+#[test]
+fn test_symbolic_input() {
+    use crate::intcode::assemble;
+    let program = assemble(
+        "
+            in [x]
+            mul [x], #3, [y]
+            add [y], #-4, [y]
+            out [y]
+            lt [y], #10, [z]
+            jt [z], #0
+            hlt
+        x:  .data 0
+        y:  .data 0
+        z:  .data 0
+        ",
+    )
+    .unwrap();
+    let mut computer = SymbolicComputer::load(program);
+    computer.provide_input(Expression::symbol("x"));
+    let error = computer.run().unwrap_err();
+    let output = computer.output()[0].clone();
+    assert_eq!(output.to_string(), "((x * 3) + -4)");
+    assert_eq!(
+        error,
+        SymbolicError::SymbolicBranch {
+            address: 16,
+            condition: Expression::less_than(output.clone(), Expression::Constant(10)),
+        }
+    );
+    let bounds = vec![("x", -100..=100)].into_iter().collect();
+    assert_eq!(output.linear().unwrap().solve(11, &bounds).unwrap()["x"], 5);
+    assert_eq!(output.linear().unwrap().solve(12, &bounds), None);
+}
@@ -0,0 +1,165 @@
+use crate::intcode::disassembler::write_instruction;
+use crate::intcode::{Cell, Parameter, Step, Value};
+use arrayvec::ArrayVec;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+
+/// A single executed instruction, as recorded by a `Tracer`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TraceEntry<V: Cell = Value> {
+    pub step: Step<V>,
+    /// The decoded parameters, as they were before the instruction was executed.
+    pub parameters: ArrayVec<[Parameter<V>; 3]>,
+    pub relative_base_before: V,
+    pub relative_base: V,
+}
+
+impl<V: Cell> fmt::Display for TraceEntry<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>5}: ", self.step.address)?;
+        write_instruction(f, self.step.op_code, &self.parameters)?;
+        for read in &self.step.reads {
+            write!(f, " | [{}] = {}", read.address, read.value)?;
+        }
+        if let Some(write) = &self.step.write {
+            write!(f, " | [{}] {} -> {}", write.address, write.old, write.new)?;
+        }
+        if self.relative_base != self.relative_base_before {
+            write!(
+                f,
+                " | rb {} -> {}",
+                self.relative_base_before, self.relative_base
+            )?;
+        }
+        if let Some(input) = &self.step.input {
+            write!(f, " | in {}", input)?;
+        }
+        if let Some(output) = &self.step.output {
+            write!(f, " | out {}", output)?;
+        }
+        Ok(())
+    }
+}
+
+/// A sink for trace entries.
+pub trait Tracer<V: Cell = Value> {
+    fn trace(&mut self, entry: &TraceEntry<V>);
+}
+
+impl<V: Cell, F: FnMut(&TraceEntry<V>)> Tracer<V> for F {
+    fn trace(&mut self, entry: &TraceEntry<V>) {
+        self(entry)
+    }
+}
+
+/// Keeps the most recent trace entries in memory.
+#[derive(Clone, Debug)]
+pub struct RingBuffer {
+    capacity: usize,
+    entries: VecDeque<TraceEntry>,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// The recorded entries, oldest first.
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+}
+
+impl Tracer for RingBuffer {
+    fn trace(&mut self, entry: &TraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry.clone());
+    }
+}
+
+/// Writes every trace entry as a line of text.
+///
+/// Tracing cannot fail, so the first error is kept and no more writes are attempted after it.
+pub struct TraceWriter<W: io::Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> TraceWriter<W> {
+    pub fn new(writer: W) -> Self {
+        TraceWriter {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Flush and return the writer, or the first error that occurred.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<V: Cell, W: io::Write> Tracer<V> for TraceWriter<W> {
+    fn trace(&mut self, entry: &TraceEntry<V>) {
+        if self.error.is_none() {
+            if let Err(error) = writeln!(self.writer, "{}", entry) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+#[futures_await_test::async_test]
+async fn test_trace_writer() {
+    use crate::intcode::{parse_program, Computer};
+    let program = parse_program("3,11,109,4,1201,7,3,12,4,12,99,0,0").unwrap();
+    let mut input: &[Value] = &[7];
+    let mut tracer = TraceWriter::new(Vec::new());
+    let mut computer = Computer::load(program);
+    computer.set_input(Some(&mut input));
+    computer.set_tracer(Some(&mut tracer));
+    computer.run().await.unwrap();
+    drop(computer);
+    assert_eq!(
+        String::from_utf8(tracer.into_inner().unwrap()).unwrap(),
+        "    0: in [11] | [11] 0 -> 7 | in 7\n\
+         \x20   2: rbo #4 | rb 0 -> 4\n\
+         \x20   4: add rb+7, #3, [12] | [11] = 7 | [12] 0 -> 10\n\
+         \x20   8: out [12] | [12] = 10 | out 10\n\
+         \x20  10: hlt\n"
+    );
+}
+
+#[futures_await_test::async_test]
+async fn test_ring_buffer_and_callback() {
+    use crate::intcode::{parse_program, Computer, OpCode};
+    let program = parse_program("1101,1,1,7,1101,2,2,7,99").unwrap();
+    let mut buffer = RingBuffer::new(2);
+    let mut computer = Computer::load(program.clone());
+    computer.set_tracer(Some(&mut buffer));
+    computer.run().await.unwrap();
+    drop(computer);
+    let addresses: Vec<_> = buffer.entries().map(|entry| entry.step.address).collect();
+    assert_eq!(addresses, vec![4, 8]);
+
+    let mut op_codes = Vec::new();
+    let mut callback = |entry: &TraceEntry| op_codes.push(entry.step.op_code);
+    let mut computer = Computer::load(program);
+    computer.set_tracer(Some(&mut callback));
+    computer.run().await.unwrap();
+    drop(computer);
+    assert_eq!(op_codes, vec![OpCode::Add, OpCode::Add, OpCode::Quit]);
+}
@@ -0,0 +1,441 @@
+//! Recording and replaying the input and output of a `Computer`.
+//!
+//! A `Recorder` wraps the reader and writer of a computer and logs every value that passes
+//! through them. When it is also installed as the tracer, every event is logged with the number of
+//! instructions executed so far, so a replay can check that the program got there the same way. A
+//! `Replayer` feeds the recorded input back and reports the first output that differs. Runs that
+//! are only sometimes recorded connect the computer through a `Connection`.
+
+use crate::intcode::{io, Computer, ComputerError, IoError, Memory, TraceEntry, Tracer, Value};
+use async_trait::async_trait;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use thiserror::*;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Event {
+    Input(Value),
+    Output(Value),
+}
+
+/// An event and the number of instructions that had been executed when it happened.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct TranscriptEntry {
+    pub step: u64,
+    pub event: Event,
+}
+
+impl fmt::Display for TranscriptEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.event {
+            Event::Input(value) => write!(f, "{} in {}", self.step, value),
+            Event::Output(value) => write!(f, "{} out {}", self.step, value),
+        }
+    }
+}
+
+/// The input and output of a run, as text with one event per line, like `120 out 7`. Empty lines
+/// and lines starting with `#` are ignored.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Transcript {
+    pub entries: Vec<TranscriptEntry>,
+}
+
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("could not parse line {line} of the transcript: {text:?}")]
+pub struct ParseTranscriptError {
+    pub line: usize,
+    pub text: String,
+}
+
+impl FromStr for Transcript {
+    type Err = ParseTranscriptError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = Vec::new();
+        for (index, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || ParseTranscriptError {
+                line: index + 1,
+                text: line.to_string(),
+            };
+            let mut words = line.split_whitespace();
+            let (step, kind, value) = match (words.next(), words.next(), words.next(), words.next())
+            {
+                (Some(step), Some(kind), Some(value), None) => (step, kind, value),
+                _ => return Err(error()),
+            };
+            let step = step.parse().map_err(|_| error())?;
+            let value = value.parse().map_err(|_| error())?;
+            let event = match kind {
+                "in" => Event::Input(value),
+                "out" => Event::Output(value),
+                _ => return Err(error()),
+            };
+            entries.push(TranscriptEntry { step, event });
+        }
+        Ok(Transcript { entries })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Recording {
+    steps: u64,
+    transcript: Transcript,
+}
+
+/// Records a transcript. Clones share the same transcript.
+#[derive(Clone, Debug, Default)]
+pub struct Recorder {
+    recording: Arc<Mutex<Recording>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder::default()
+    }
+
+    /// Wrap the reader of a computer to record its input.
+    pub fn input<R: io::Read + Send>(&self, read: R) -> Recorded<R> {
+        Recorded {
+            inner: read,
+            recorder: self.clone(),
+        }
+    }
+
+    /// Wrap the writer of a computer to record its output.
+    pub fn output<W: io::Write + Send>(&self, write: W) -> Recorded<W> {
+        Recorded {
+            inner: write,
+            recorder: self.clone(),
+        }
+    }
+
+    pub fn transcript(&self) -> Transcript {
+        self.recording.lock().unwrap().transcript.clone()
+    }
+
+    fn record(&self, event: Event) {
+        let mut recording = self.recording.lock().unwrap();
+        let step = recording.steps;
+        recording
+            .transcript
+            .entries
+            .push(TranscriptEntry { step, event });
+    }
+}
+
+/// Counts the executed instructions, for the steps of the events.
+impl Tracer for Recorder {
+    fn trace(&mut self, _: &TraceEntry) {
+        self.recording.lock().unwrap().steps += 1;
+    }
+}
+
+/// A reader or writer whose values are recorded.
+pub struct Recorded<T> {
+    inner: T,
+    recorder: Recorder,
+}
+
+#[async_trait]
+impl<R: io::Read + Send> io::Read for Recorded<R> {
+    async fn read(&mut self) -> Result<Option<Value>, IoError> {
+        let value = self.inner.read().await?;
+        if let Some(value) = value {
+            self.recorder.record(Event::Input(value));
+        }
+        Ok(value)
+    }
+}
+
+#[async_trait]
+impl<W: io::Write + Send> io::Write for Recorded<W> {
+    async fn write(&mut self, output: Value) -> Result<(), IoError> {
+        self.inner.write(output).await?;
+        self.recorder.record(Event::Output(output));
+        Ok(())
+    }
+}
+
+/// The reader and writer of a computer, wrapped only if they are recorded.
+pub enum Connection<R, W> {
+    Direct(R, W),
+    Recorded(Recorded<R>, Recorded<W>, Recorder),
+}
+
+impl<R: io::Read + Send, W: io::Write + Send> Connection<R, W> {
+    /// Connect `read` and `write`, recording them with `recorder`, if any.
+    pub fn new(read: R, write: W, recorder: Option<&Recorder>) -> Self {
+        match recorder {
+            Some(recorder) => Connection::Recorded(
+                recorder.input(read),
+                recorder.output(write),
+                recorder.clone(),
+            ),
+            None => Connection::Direct(read, write),
+        }
+    }
+
+    /// Install the reader and writer in `computer`, and the recorder as its tracer.
+    pub fn attach<'a>(&'a mut self, computer: &mut Computer<'a>) {
+        match self {
+            Connection::Direct(read, write) => {
+                computer.set_input(Some(read));
+                computer.set_output(Some(write));
+            }
+            Connection::Recorded(read, write, recorder) => {
+                computer.set_input(Some(read));
+                computer.set_output(Some(write));
+                computer.set_tracer(Some(recorder));
+            }
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum ReplayError {
+    #[error("event {index}: expected `{expected}`, but got `{actual}`")]
+    Mismatch {
+        index: usize,
+        expected: TranscriptEntry,
+        actual: TranscriptEntry,
+    },
+    #[error("event {index}: expected `{expected}`, but the program wants input at step {step}")]
+    UnexpectedInput {
+        index: usize,
+        expected: TranscriptEntry,
+        step: u64,
+    },
+    #[error("event {index}: got `{actual}` after the end of the transcript")]
+    Unexpected {
+        index: usize,
+        actual: TranscriptEntry,
+    },
+    #[error("the program stopped after {events} of {expected} events")]
+    Incomplete { events: usize, expected: usize },
+    #[error("the program failed: {0}")]
+    Computer(#[from] ComputerError),
+}
+
+#[derive(Debug)]
+struct Replay {
+    transcript: Transcript,
+    position: usize,
+    steps: u64,
+    error: Option<ReplayError>,
+    /// Whether the program asked for input after the end of the transcript.
+    ended: bool,
+}
+
+/// Feeds the input of a transcript to a computer and checks its output against it. Install it as
+/// the reader, writer and tracer of the computer, using clones, which share the same replay.
+#[derive(Clone, Debug)]
+pub struct Replayer {
+    replay: Arc<Mutex<Replay>>,
+}
+
+impl Replayer {
+    pub fn new(transcript: Transcript) -> Self {
+        Replayer {
+            replay: Arc::new(Mutex::new(Replay {
+                transcript,
+                position: 0,
+                steps: 0,
+                error: None,
+                ended: false,
+            })),
+        }
+    }
+
+    /// The first difference from the transcript, or an error if the transcript was not used up.
+    pub fn finish(&self) -> Result<(), ReplayError> {
+        let replay = self.replay.lock().unwrap();
+        if let Some(error) = &replay.error {
+            return Err(error.clone());
+        }
+        let expected = replay.transcript.entries.len();
+        if replay.position < expected {
+            return Err(ReplayError::Incomplete {
+                events: replay.position,
+                expected,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Replay {
+    fn expect(&mut self, actual: TranscriptEntry) -> bool {
+        if self.error.is_some() {
+            return false;
+        }
+        let index = self.position;
+        self.error = match self.transcript.entries.get(index) {
+            Some(expected) if *expected == actual => None,
+            Some(expected) => Some(ReplayError::Mismatch {
+                index,
+                expected: *expected,
+                actual,
+            }),
+            None => Some(ReplayError::Unexpected { index, actual }),
+        };
+        self.position += 1;
+        self.error.is_none()
+    }
+}
+
+#[async_trait]
+impl io::Read for Replayer {
+    /// Ends the input at the first difference, which `finish` reports.
+    async fn read(&mut self) -> Result<Option<Value>, IoError> {
+        let mut replay = self.replay.lock().unwrap();
+        let index = replay.position;
+        let step = replay.steps;
+        match replay.transcript.entries.get(index).copied() {
+            Some(
+                entry @ TranscriptEntry {
+                    event: Event::Input(value),
+                    ..
+                },
+            ) if entry.step == step => {
+                replay.expect(entry);
+                Ok(Some(value))
+            }
+            Some(expected) if replay.error.is_none() => {
+                replay.error = Some(ReplayError::UnexpectedInput {
+                    index,
+                    expected,
+                    step,
+                });
+                Ok(None)
+            }
+            None => {
+                replay.ended = true;
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl io::Write for Replayer {
+    async fn write(&mut self, output: Value) -> Result<(), IoError> {
+        let mut replay = self.replay.lock().unwrap();
+        let step = replay.steps;
+        replay.expect(TranscriptEntry {
+            step,
+            event: Event::Output(output),
+        });
+        Ok(())
+    }
+}
+
+impl Tracer for Replayer {
+    fn trace(&mut self, _: &TraceEntry) {
+        self.replay.lock().unwrap().steps += 1;
+    }
+}
+
+/// Run `memory` on the input of `transcript`, checking that it produces the same output at the
+/// same steps. The replay ends when the program halts or asks for input after the end of the
+/// transcript, so a transcript can cover just the start of a session.
+pub async fn replay(memory: Memory, transcript: Transcript) -> Result<(), ReplayError> {
+    let replayer = Replayer::new(transcript);
+    let (mut input, mut output, mut tracer) =
+        (replayer.clone(), replayer.clone(), replayer.clone());
+    let mut computer = Computer::load(memory);
+    computer.set_input(Some(&mut input));
+    computer.set_output(Some(&mut output));
+    computer.set_tracer(Some(&mut tracer));
+    let result = computer.run().await;
+    drop(computer);
+    // A difference in the output is more useful than the error it causes
+    replayer.finish()?;
+    match result {
+        Err(error)
+            if error.kind() == &ComputerError::ReadInputError
+                && replayer.replay.lock().unwrap().ended =>
+        {
+            Ok(())
+        }
+        result => result.map_err(ReplayError::from),
+    }
+}
+
+#[futures_await_test::async_test]
+async fn test_record_and_replay() {
+    use crate::intcode::assemble;
+    let program = assemble(
+        "
+        loop:   in [n]
+                jf [n], #end
+                mul [n], #2, [n]
+                out [n]
+                jt #1, #loop
+        end:    hlt
+        n:      .data 0
+        ",
+    )
+    .unwrap();
+    let recorder = Recorder::new();
+    let mut input = recorder.input(&[3, 5, 0][..]);
+    let mut output = recorder.output(Vec::new());
+    let mut tracer = recorder.clone();
+    let mut computer = Computer::load(program.clone());
+    computer.set_input(Some(&mut input));
+    computer.set_output(Some(&mut output));
+    computer.set_tracer(Some(&mut tracer));
+    computer.run().await.unwrap();
+    drop(computer);
+    assert_eq!(output.inner, vec![6, 10]);
+
+    let transcript = recorder.transcript();
+    let text = "0 in 3\n4 out 6\n5 in 5\n9 out 10\n10 in 0\n";
+    assert_eq!(transcript.to_string(), text);
+    assert_eq!(
+        format!("# recorded\n\n{}", text).parse::<Transcript>(),
+        Ok(transcript.clone())
+    );
+    assert_eq!(
+        "0 in 3\n4 out".parse::<Transcript>(),
+        Err(ParseTranscriptError {
+            line: 2,
+            text: "4 out".to_string()
+        })
+    );
+    assert_eq!(replay(program.clone(), transcript).await, Ok(()));
+
+    let changed: Transcript = "0 in 3\n4 out 7\n".parse().unwrap();
+    assert_eq!(
+        replay(program.clone(), changed)
+            .await
+            .unwrap_err()
+            .to_string(),
+        "event 1: expected `4 out 7`, but got `4 out 6`"
+    );
+    let start: Transcript = "0 in 3\n4 out 6\n".parse().unwrap();
+    assert_eq!(replay(program.clone(), start).await, Ok(()));
+    let missing: Transcript = "0 in 3\n4 out 6\n5 in 0\n9 out 0\n".parse().unwrap();
+    assert_eq!(
+        replay(program, missing).await,
+        Err(ReplayError::Incomplete {
+            events: 3,
+            expected: 4
+        })
+    );
+}
@@ -19,3 +19,40 @@ async fn test_part_2() -> Result<()> {
     assert_eq!(part_2(parse_input(&s)?).await?, 7408802);
     Ok(())
 }
+
+#[test]
+fn test_coverage() -> Result<()> {
+    use adventofcode::intcode::{Computer, Coverage, OpCode, Status};
+    let mut s = String::new();
+    File::open("./input/day5")?.read_to_string(&mut s)?;
+    let program = parse_input(&s)?;
+    let mut coverage = Coverage::new();
+    for input in &[1, 5] {
+        let mut run = Coverage::new();
+        let mut computer = Computer::load(program.clone());
+        computer.set_tracer(Some(&mut run));
+        computer.provide_input(*input);
+        while computer.resume()? != Status::Halted {}
+        drop(computer);
+        coverage.merge(&run);
+    }
+    // Both parts together exercise every comparison and jump
+    for op_code in &[
+        OpCode::LessThan,
+        OpCode::Equals,
+        OpCode::JumpIfTrue,
+        OpCode::JumpIfFalse,
+    ] {
+        assert!(coverage.op_code_executions(*op_code) > 0);
+    }
+    // The instruction at 10 only exists after the program patched itself, and no branch went
+    // both ways
+    assert_eq!(coverage.executions(10), 1);
+    assert_eq!(coverage.executions(247), 1);
+    let listing = coverage.annotate(&program).to_string();
+    assert!(
+        listing.contains("           1 !  247: jt [227], #99999         taken 0, not taken 1\n")
+    );
+    assert!(listing.ends_with("161 of 161 instructions and 0 of 28 branches covered\n"));
+    Ok(())
+}
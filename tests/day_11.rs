@@ -1,4 +1,4 @@
-pub use adventofcode::day_11::{parse_input, part_1, part_2};
+pub use adventofcode::day_11::{parse_input, part_1, part_2, record_part_2};
 use anyhow::Result;
 use futures_await_test::async_test;
 
@@ -33,3 +33,17 @@ async fn test_part_2() -> Result<()> {
     );
     Ok(())
 }
+
+#[async_test]
+async fn test_transcript() -> Result<()> {
+    use adventofcode::intcode::{replay, Transcript};
+    use std::fs;
+    let memory = parse_input(&fs::read_to_string("input/day11")?)?;
+    let transcript: Transcript =
+        fs::read_to_string("tests/transcripts/day_11_part_2.txt")?.parse()?;
+    let mut recorded = record_part_2(memory.clone()).await?;
+    recorded.entries.truncate(transcript.entries.len());
+    assert_eq!(recorded, transcript);
+    replay(memory, transcript).await?;
+    Ok(())
+}
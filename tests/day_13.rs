@@ -1,4 +1,4 @@
-pub use adventofcode::day_13::{parse_input, part_1, part_2};
+pub use adventofcode::day_13::{parse_input, part_1, part_2, record_part_2};
 use anyhow::Result;
 use futures_await_test::*;
 
@@ -23,3 +23,19 @@ async fn test_part_2() -> Result<()> {
     assert_eq!(part_2(parse_input(&s)?).await?, 18647);
     Ok(())
 }
+
+#[async_test]
+async fn test_transcript() -> Result<()> {
+    use adventofcode::intcode::{replay, Transcript};
+    use std::fs;
+    let mut memory = parse_input(&fs::read_to_string("input/day13")?)?;
+    let transcript: Transcript =
+        fs::read_to_string("tests/transcripts/day_13_part_2.txt")?.parse()?;
+    let mut recorded = record_part_2(memory.clone()).await?;
+    recorded.entries.truncate(transcript.entries.len());
+    assert_eq!(recorded, transcript);
+    // Insert coins
+    *memory.get_mut(0) = 2;
+    replay(memory, transcript).await?;
+    Ok(())
+}
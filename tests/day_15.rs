@@ -1,4 +1,4 @@
-pub use adventofcode::day_15::{parse_input, part_1, part_2};
+pub use adventofcode::day_15::{parse_input, part_1, part_2, record_part_1};
 use anyhow::Result;
 use futures_await_test::*;
 
@@ -23,3 +23,21 @@ async fn test_part_2() -> Result<()> {
     assert_eq!(part_2(parse_input(&s)?).await?, 348);
     Ok(())
 }
+
+#[async_test]
+async fn test_transcript() -> Result<()> {
+    use adventofcode::intcode::{replay, Transcript};
+    use std::fs;
+    let memory = parse_input(&fs::read_to_string("input/day15")?)?;
+    let transcript: Transcript =
+        fs::read_to_string("tests/transcripts/day_15_part_1.txt")?.parse()?;
+    let recorded = record_part_1(memory.clone()).await?;
+    assert_eq!(
+        recorded.entries[..transcript.entries.len()],
+        transcript.entries[..]
+    );
+    replay(memory.clone(), transcript).await?;
+    // The whole recording replays as well
+    replay(memory, recorded).await?;
+    Ok(())
+}